@@ -4,8 +4,12 @@ use csv::StringRecord;
 use std::fs::File;
 use steel_cent::formatting;
 
-use roommates::sharing::Bill;
 use roommates::sharing::SharingData::{Fixed, Variable};
+use roommates::sharing::{
+    Bill, Covariates, DailyTemperatures, DegreeDays, EstimationConfig, EstimationFallback,
+    OrdinaryLeastSquares,
+};
+use roommates::splitting::LargestRemainder;
 use roommates::RoommateGroup;
 use roommates::{DateInterval, ResponsibilityInterval, ResponsibilityRecord};
 
@@ -73,10 +77,18 @@ fn main() {
     if let Some(file_name) = matches.value_of("water bill") {
         let mut water_bills = build_bills(file_name)
             .into_iter()
-            .map(|b| (b, None))
+            .map(|b| (b, Covariates::new()))
             .collect::<Vec<_>>();
         let current_water = water_bills.remove(water_bills.len() - current_bill_position_from_end);
-        bills.push(("water", Variable(current_water, water_bills)));
+        bills.push((
+            "water",
+            Variable(
+                current_water,
+                water_bills,
+                Box::new(OrdinaryLeastSquares),
+                EstimationConfig::default(),
+            ),
+        ));
     }
     if let Some(file_name) = matches.value_of("internet bill") {
         let mut internet_bills = build_bills(file_name);
@@ -85,22 +97,43 @@ fn main() {
         bills.push(("internet", Fixed(current_internet)));
     }
     if let Some(file_name) = matches.value_of("electric bill") {
-        let weather_data = WeatherData::build(matches.value_of("weather data").unwrap());
+        let temperatures = build_temperatures(matches.value_of("weather data").unwrap());
         let mut electric_bills = build_bills(file_name)
             .into_iter()
-            .map(|bill| {
-                let ti = Some(weather_data.calculate_temperature_index(&bill));
-                (bill, ti)
-            })
+            .map(|b| (b, Covariates::new()))
             .collect::<Vec<_>>();
         let current_electric =
             electric_bills.remove(electric_bills.len() - current_bill_position_from_end);
-        bills.push(("electric", Variable(current_electric, electric_bills)));
+        bills.push((
+            "electric",
+            Variable(
+                current_electric,
+                electric_bills,
+                Box::new(DegreeDays::new(temperatures)),
+                // a year of history leaves only a bill or two for each season,
+                // so an estimate outside of the bill is reported rather than
+                // clamped into it
+                EstimationConfig {
+                    max_prediction_error: 0.3,
+                    clamp: false,
+                    fallback: EstimationFallback::SplitEvenly,
+                    ..EstimationConfig::default()
+                },
+            ),
+        ));
     }
-    let invoices = roommates.generate_invoices(bills, &intervals);
-    for invoice in invoices.iter() {
+    let invoices = roommates
+        .generate_invoices(bills, &intervals, &mut LargestRemainder)
+        .expect("could not generate invoices");
+    for invoice in invoices.invoices() {
         println!("{}", invoice);
     }
+    for (label, report) in invoices.estimation_reports() {
+        println!("{} bill {}", label, report);
+    }
+    for (label, error) in invoices.failed_bills() {
+        eprintln!("could not split the {} bill: {}", label, error);
+    }
 }
 
 trait FromStringRecord {
@@ -123,8 +156,8 @@ impl FromStringRecord for Bill {
     }
 }
 
-fn build_intervals<'a, 'b>(
-    file_name: &'a str,
+fn build_intervals<'b>(
+    file_name: &str,
     roommate_group: &'b RoommateGroup,
 ) -> ResponsibilityRecord<'b> {
     let mut rdr = csv::ReaderBuilder::new()
@@ -168,38 +201,16 @@ fn build_bills(file_name: &str) -> Vec<Bill> {
         .collect::<Vec<_>>()
 }
 
-struct WeatherData {
-    data: Vec<(NaiveDate, f64, f64)>,
-}
-impl WeatherData {
-    fn build(file_name: &str) -> Self {
-        let mut rdr = csv::ReaderBuilder::new()
-            .from_reader(File::open(file_name).expect("Could not find weather file"));
-        let data = rdr
-            .records()
-            .map(|r| r.expect("bad record"))
-            .map(|r| {
-                (
-                    NaiveDate::parse_from_str(r.get(2).unwrap(), "%Y-%m-%d").expect("bad date"),
-                    r.get(3).unwrap().parse::<f64>().unwrap(),
-                    r.get(4).unwrap().parse::<f64>().unwrap(),
-                )
-            })
-            .collect::<Vec<_>>();
-        WeatherData { data }
-    }
-
-    fn calculate_temperature_index(&self, bill: &Bill) -> f64 {
-        let (start, end) = (bill.usage_period().start(), bill.usage_period().end());
-        self.data
-            .iter()
-            .filter(|(x, _, _)| x > &start && x < &end)
-            .fold(0.0, |a, (_, low, high)| {
-                a + temperature_index((*low, *high))
-            })
-    }
-}
-
-fn temperature_index((low, high): (f64, f64)) -> f64 {
-    ((low + high) / 2.0 - 70.0).powf(2.0)
+fn build_temperatures(file_name: &str) -> DailyTemperatures {
+    let mut rdr = csv::ReaderBuilder::new()
+        .from_reader(File::open(file_name).expect("Could not find weather file"));
+    rdr.records()
+        .map(|r| r.expect("bad record"))
+        .map(|r| {
+            let date = NaiveDate::parse_from_str(r.get(2).unwrap(), "%Y-%m-%d").expect("bad date");
+            let high = r.get(3).unwrap().parse::<f64>().unwrap();
+            let low = r.get(4).unwrap().parse::<f64>().unwrap();
+            (date, (low + high) / 2.0)
+        })
+        .collect()
 }
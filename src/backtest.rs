@@ -0,0 +1,343 @@
+use std::fmt;
+use steel_cent::Money;
+
+use super::bill::{Bill, SharedBill};
+use super::estimation::{Covariates, EstimationModel};
+use super::interval::DateInterval;
+use super::shared_cost::{EstimationConfig, EstimationReport};
+use crate::Error;
+
+/// How a model would have estimated each bill of a history, using only the
+/// bills before it
+///
+/// Starting after the first `min_history` bills (or from the first bill, for
+/// a model that handles short histories itself), each bill is estimated with
+/// [`SharedBill::from_estimate`] as if it were the current bill, so the
+/// estimates are accepted or fall back just as they would when invoicing.
+///
+/// # Examples
+/// ```
+/// use roommates::DateInterval;
+/// use roommates::sharing::{Backtest, Bill, Covariates, EstimationConfig, OrdinaryLeastSquares};
+/// use steel_cent::{Money, currency::USD};
+///
+/// let bills = (1..=8)
+///     .map(|month| {
+///         let period = DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap();
+///         Bill::new(Money::of_minor(USD, 10_00 + 5_00 * (month % 3) as i64), period)
+///     })
+///     .collect::<Vec<_>>();
+/// let none = Covariates::new();
+/// let history = bills.iter().zip(1..).map(|(bill, month)| (bill, month % 3, &none));
+/// let backtest = Backtest::run(history, &OrdinaryLeastSquares, &EstimationConfig::default());
+/// assert_eq!(backtest.steps().len(), 4);
+/// assert!(backtest.mean_absolute_prediction_error().unwrap() < 0.01);
+/// ```
+///
+/// [`SharedBill::from_estimate`]: struct.SharedBill.html#method.from_estimate
+pub struct Backtest {
+    model: String,
+    steps: Vec<BacktestStep>,
+}
+
+/// The estimate of one bill in a [`Backtest`]
+///
+/// [`Backtest`]: struct.Backtest.html
+pub struct BacktestStep {
+    usage_period: DateInterval,
+    amount_due: Money,
+    result: Result<(Money, EstimationReport), Error>,
+}
+
+impl Backtest {
+    /// Estimates each bill of `history` (in order) from the bills before it
+    pub fn run<'a, I>(history: I, model: &dyn EstimationModel, config: &EstimationConfig) -> Self
+    where
+        I: IntoIterator<Item = (&'a Bill, u32, &'a Covariates)>,
+    {
+        let history = history.into_iter().collect::<Vec<_>>();
+        let first = if model.handles_short_history() {
+            0
+        } else {
+            config.min_history
+        };
+        let steps = (first..history.len())
+            .map(|i| {
+                let (bill, occupancy, covariates) = history[i];
+                let result = SharedBill::from_estimate(
+                    (bill.clone(), (occupancy, covariates.clone())),
+                    history[..i].iter().copied(),
+                    model,
+                    config,
+                )
+                .map(|(shared_bill, report)| (shared_bill.shared_amount(), report));
+                BacktestStep {
+                    usage_period: bill.usage_period(),
+                    amount_due: bill.amount_due(),
+                    result,
+                }
+            })
+            .collect();
+        Backtest {
+            model: model.name(),
+            steps,
+        }
+    }
+
+    /// The name of the model tested
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// One step for each bill estimated, in order
+    pub fn steps(&self) -> &[BacktestStep] {
+        &self.steps
+    }
+
+    /// The number of bills whose shared cost could not be found, even with
+    /// the fallback
+    pub fn failures(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| step.result.is_err())
+            .count()
+    }
+
+    /// The mean of the absolute prediction errors of the bills that the model
+    /// predicted
+    pub fn mean_absolute_prediction_error(&self) -> Option<f64> {
+        mean(
+            self.steps
+                .iter()
+                .filter_map(BacktestStep::prediction_error)
+                .map(f64::abs),
+        )
+    }
+
+    /// The standard deviation of the shared amounts over their mean, which is
+    /// small if the model shares about the same amount of every bill
+    pub fn shared_amount_variation(&self) -> Option<f64> {
+        let shared = self.shared_amounts();
+        let mean = mean(shared.iter().copied()).filter(|mean| *mean != 0.0)?;
+        let variance = shared.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / shared.len() as f64;
+        Some(variance.sqrt() / mean.abs())
+    }
+
+    /// The mean absolute change in the shared amount from one bill to the
+    /// next over the mean shared amount, which is small if the estimate is
+    /// stable as history builds up
+    pub fn mean_shared_amount_change(&self) -> Option<f64> {
+        let shared = self.shared_amounts();
+        let mean_shared = mean(shared.iter().copied()).filter(|mean| *mean != 0.0)?;
+        let change = mean(shared.windows(2).map(|pair| (pair[1] - pair[0]).abs()))?;
+        Some(change / mean_shared.abs())
+    }
+
+    fn shared_amounts(&self) -> Vec<f64> {
+        self.steps
+            .iter()
+            .filter_map(|step| Some(step.shared_amount()?.minor_amount() as f64))
+            .collect()
+    }
+}
+
+impl BacktestStep {
+    /// The usage period of the bill
+    pub fn usage_period(&self) -> DateInterval {
+        self.usage_period
+    }
+
+    /// The amount due of the bill
+    pub fn amount_due(&self) -> Money {
+        self.amount_due
+    }
+
+    /// The shared amount of the bill, unless it could not be found
+    pub fn shared_amount(&self) -> Option<Money> {
+        self.result.as_ref().ok().map(|(shared, _)| *shared)
+    }
+
+    /// How the shared amount was estimated, unless it could not be found
+    pub fn report(&self) -> Option<&EstimationReport> {
+        self.result.as_ref().ok().map(|(_, report)| report)
+    }
+
+    /// Why the shared amount could not be found
+    pub fn error(&self) -> Option<&Error> {
+        self.result.as_ref().err()
+    }
+
+    /// (predicted - actual) / actual for the amount due, if the model
+    /// predicted it and it isn't zero
+    pub fn prediction_error(&self) -> Option<f64> {
+        let predicted = self.report()?.estimate()?.predicted?;
+        let actual = self.amount_due.minor_amount() as f64;
+        if actual == 0.0 {
+            None
+        } else {
+            Some((predicted - actual) / actual)
+        }
+    }
+}
+
+/// Backtests of several models over the same history, to compare them side
+/// by side
+pub struct BacktestComparison(Vec<Backtest>);
+
+impl BacktestComparison {
+    /// Runs a [`Backtest`] of each model
+    ///
+    /// [`Backtest`]: struct.Backtest.html
+    pub fn run<'a, I>(
+        history: I,
+        models: &[&dyn EstimationModel],
+        config: &EstimationConfig,
+    ) -> Self
+    where
+        I: IntoIterator<Item = (&'a Bill, u32, &'a Covariates)>,
+    {
+        let history = history.into_iter().collect::<Vec<_>>();
+        BacktestComparison(
+            models
+                .iter()
+                .map(|model| Backtest::run(history.iter().copied(), *model, config))
+                .collect(),
+        )
+    }
+
+    /// The backtest of each model, in the order the models were given
+    pub fn backtests(&self) -> &[Backtest] {
+        &self.0
+    }
+}
+
+fn mean<I: Iterator<Item = f64>>(values: I) -> Option<f64> {
+    let (count, total) = values.fold((0, 0.0), |(count, total), value| (count + 1, total + value));
+    if count == 0 {
+        None
+    } else {
+        Some(total / count as f64)
+    }
+}
+
+/// Formats a statistic that might be missing as a percentage
+struct Percent(Option<f64>);
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "{:.1}%", value * 100.0),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+impl fmt::Display for Backtest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "backtest of {}", self.model)?;
+        for step in &self.steps {
+            write!(
+                f,
+                "\n\t{} to {}: {} due",
+                step.usage_period.start(),
+                step.usage_period.end(),
+                step.amount_due
+            )?;
+            match &step.result {
+                Ok((shared, _)) => write!(
+                    f,
+                    ", {} shared, prediction error {}",
+                    shared,
+                    Percent(step.prediction_error())
+                )?,
+                Err(error) => write!(f, ", failed: {}", error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BacktestComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "model\tbills\tfailed\tmean |prediction error|\tshared amount variation\tmean shared amount change"
+        )?;
+        for backtest in &self.0 {
+            write!(
+                f,
+                "\n{}\t{}\t{}\t{}\t{}\t{}",
+                backtest.model,
+                backtest.steps.len(),
+                backtest.failures(),
+                Percent(backtest.mean_absolute_prediction_error()),
+                Percent(backtest.shared_amount_variation()),
+                Percent(backtest.mean_shared_amount_change())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::estimation::{HuberLeastSquares, OrdinaryLeastSquares};
+    use crate::shared_cost::EstimationFallback;
+    use steel_cent::currency::USD;
+
+    #[test]
+    fn compare_models() {
+        // $10 shared plus $10 per person, with a catch-up bill in March
+        let bills = [1, 2, 3, 1, 2, 3, 1, 2, 3, 1]
+            .iter()
+            .zip(1..)
+            .map(|(people, month)| {
+                let period = DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap();
+                let catch_up = if month == 3 { 40_00 } else { 0 };
+                let cost = 10_00 + 10_00 * people + catch_up + month as i64;
+                (
+                    Bill::new(Money::of_minor(USD, cost), period),
+                    *people as u32,
+                )
+            })
+            .collect::<Vec<_>>();
+        let none = Covariates::new();
+        let config = EstimationConfig {
+            fallback: EstimationFallback::SplitEvenly,
+            ..EstimationConfig::default()
+        };
+        let comparison = BacktestComparison::run(
+            bills.iter().map(|(bill, people)| (bill, *people, &none)),
+            &[&OrdinaryLeastSquares, &HuberLeastSquares::default()],
+            &config,
+        );
+        let (ols, huber) = match comparison.backtests() {
+            [ols, huber] => (ols, huber),
+            _ => panic!("one backtest per model"),
+        };
+        assert_eq!(ols.steps().len(), 6);
+        assert_eq!(ols.steps()[0].usage_period(), bills[4].0.usage_period());
+        assert_eq!(huber.failures(), 0);
+        // least squares is thrown off by the catch-up bill and falls back
+        assert!(ols
+            .steps()
+            .iter()
+            .all(|step| step.shared_amount() == Some(step.amount_due())));
+        // Huber regression needs a couple more bills to discount it
+        for step in &huber.steps()[2..] {
+            assert!(step.prediction_error().unwrap().abs() < 0.01);
+            assert!(
+                (step.shared_amount().unwrap() - Money::of_minor(USD, 10_00))
+                    .minor_amount()
+                    .abs()
+                    < 5
+            );
+        }
+        assert!(
+            huber.mean_absolute_prediction_error().unwrap()
+                < ols.mean_absolute_prediction_error().unwrap()
+        );
+        assert_eq!(comparison.to_string().lines().count(), 3);
+    }
+}
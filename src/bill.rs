@@ -1,6 +1,7 @@
 use steel_cent::Money;
 
 use super::interval::DateInterval;
+use super::tariff::Tariff;
 use crate::{Error, InvalidFixedCost::*};
 
 /// Stores information about a bill
@@ -9,6 +10,8 @@ pub struct Bill {
     amount_due: Money,
     fixed_cost: Money,
     usage_period: DateInterval,
+    usage: Option<f64>,
+    tariff: Option<Tariff>,
 }
 
 impl Bill {
@@ -77,9 +80,38 @@ impl Bill {
             amount_due,
             fixed_cost,
             usage_period,
+            usage: None,
+            tariff: None,
         })
     }
 
+    /// Records the metered usage (such as kWh, gallons or therms) over the
+    /// usage period
+    ///
+    /// # Examples
+    /// ```
+    /// use roommates::{DateInterval, sharing::{Bill, Tariff}};
+    /// use steel_cent::{Money, currency::USD};
+    ///
+    /// let electric_bill = Bill::new(
+    ///     Money::of_minor(USD, 61_50),
+    ///     DateInterval::new((2020, 4, 15), (2020, 5, 15)).unwrap(),
+    /// )
+    /// .with_usage(350.0)
+    /// .with_tariff(Tariff::new(Money::of_minor(USD, 12_50), 14.0));
+    /// assert_eq!(electric_bill.usage(), Some(350.0));
+    /// ```
+    pub fn with_usage(mut self, usage: f64) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Records the tariff that the bill was charged under
+    pub fn with_tariff(mut self, tariff: Tariff) -> Self {
+        self.tariff = Some(tariff);
+        self
+    }
+
     /// `fixed_cost` represents an explicitly usage-independent portion of the
     /// bill, such as a security deposit
     ///
@@ -141,6 +173,16 @@ impl Bill {
     pub fn usage_period(&self) -> DateInterval {
         self.usage_period
     }
+
+    /// Returns the metered usage, if it was recorded
+    pub fn usage(&self) -> Option<f64> {
+        self.usage
+    }
+
+    /// Returns the tariff, if it was recorded
+    pub fn tariff(&self) -> Option<&Tariff> {
+        self.tariff.as_ref()
+    }
 }
 
 /// A Bill along with the communally shared cost
@@ -195,6 +237,28 @@ impl SharedBill {
         self.shared_amount
     }
 
+    /// Returns the explicitly usage-independent portion of the underlying
+    /// [`Bill`]
+    ///
+    /// [`Bill`]: struct.Bill.html
+    ///
+    /// # Examples
+    /// ```
+    /// use roommates::{DateInterval, sharing::{Bill, SharedBill}};
+    /// use steel_cent::{Money, currency::USD};
+    ///
+    /// let water_bill = Bill::new_with_fixed_cost(
+    ///     Money::of_minor(USD, 83_22),
+    ///     DateInterval::new((2020, 4, 15), (2020, 5, 15)).unwrap(),
+    ///     Money::of_minor(USD, 10_00),
+    /// ).unwrap();
+    /// let water_bill = SharedBill::new(water_bill, Money::of_minor(USD, 30_00)).unwrap();
+    /// assert_eq!(water_bill.fixed_cost(), Money::of_minor(USD, 10_00));
+    /// ```
+    pub fn fixed_cost(&self) -> Money {
+        self.bill.fixed_cost()
+    }
+
     /// Returns the total cost
     ///
     /// # Examples
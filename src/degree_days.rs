@@ -0,0 +1,370 @@
+use chrono::naive::NaiveDate;
+use std::collections::BTreeMap;
+use std::iter::FromIterator;
+
+use super::bill::Bill;
+use super::estimation::{Covariates, Design, Estimate, EstimationModel};
+use super::interval::DateInterval;
+use crate::{Error, EstimationError};
+
+/// The mean temperature of each day
+///
+/// # Examples
+/// ```
+/// use chrono::naive::NaiveDate;
+/// use roommates::DateInterval;
+/// use roommates::sharing::DailyTemperatures;
+///
+/// let temperatures: DailyTemperatures = vec![
+///     (NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(), 40.0),
+///     (NaiveDate::from_ymd_opt(2020, 1, 2).unwrap(), 70.0),
+/// ].into_iter().collect();
+/// let period = DateInterval::new((2020, 1, 1), (2020, 1, 2)).unwrap();
+/// assert_eq!(temperatures.heating_degree_days(period, 65.0), Ok(25.0));
+/// assert_eq!(temperatures.cooling_degree_days(period, 65.0), Ok(5.0));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DailyTemperatures {
+    means: BTreeMap<NaiveDate, f64>,
+}
+
+impl DailyTemperatures {
+    /// The total number of degrees that each day in `period` was below
+    /// `balance_temperature`
+    ///
+    /// Returns a [`MissingTemperature`] error if a day has no temperature.
+    ///
+    /// [`MissingTemperature`]: ../enum.EstimationError.html#variant.MissingTemperature
+    pub fn heating_degree_days(
+        &self,
+        period: DateInterval,
+        balance_temperature: f64,
+    ) -> Result<f64, Error> {
+        self.degree_days(period, |mean| balance_temperature - mean)
+    }
+
+    /// The total number of degrees that each day in `period` was above
+    /// `balance_temperature`
+    ///
+    /// Returns a [`MissingTemperature`] error if a day has no temperature.
+    ///
+    /// [`MissingTemperature`]: ../enum.EstimationError.html#variant.MissingTemperature
+    pub fn cooling_degree_days(
+        &self,
+        period: DateInterval,
+        balance_temperature: f64,
+    ) -> Result<f64, Error> {
+        self.degree_days(period, |mean| mean - balance_temperature)
+    }
+
+    fn degree_days<F>(&self, period: DateInterval, degrees: F) -> Result<f64, Error>
+    where
+        F: Fn(f64) -> f64,
+    {
+        period.days().try_fold(0.0, |total, date| {
+            let mean = self.means.get(&date).ok_or(Error::Estimation(
+                EstimationError::MissingTemperature { date },
+            ))?;
+            Ok(total + degrees(*mean).max(0.0))
+        })
+    }
+}
+
+impl FromIterator<(NaiveDate, f64)> for DailyTemperatures {
+    fn from_iter<I: IntoIterator<Item = (NaiveDate, f64)>>(means: I) -> Self {
+        DailyTemperatures {
+            means: means.into_iter().collect(),
+        }
+    }
+}
+
+type DegreeDaySum = fn(&DailyTemperatures, DateInterval, f64) -> Result<f64, Error>;
+
+/// Models the usage-dependent cost with occupancy and separate heating and
+/// cooling degree-day terms
+///
+/// Fits `Y ~ Oc + covariates + HDD + CDD` by least squares for each
+/// candidate balance temperature (the outdoor temperature that needs no
+/// heating or cooling) and keeps the best fit by adjusted R², since a term
+/// that is zero for every bill is left out. The shared cost is the fitted
+/// cost at zero occupancy, including heating and cooling, plus the bill's
+/// fixed cost.
+///
+/// Fits in which heating or cooling degree days lower the bill are skipped,
+/// with a [`NegativeDegreeDayCost`] error if no fit is left. If the best
+/// balance temperature is the lowest or highest of several tried, the best
+/// one probably lies outside of them, so a [`BalanceTemperatureAtEdge`]
+/// error is returned.
+///
+/// # Examples
+/// ```
+/// use roommates::sharing::{DailyTemperatures, DegreeDays};
+///
+/// let temperatures = DailyTemperatures::default();
+/// let celsius = DegreeDays::new(temperatures)
+///     .with_balance_temperatures((10..=24).map(f64::from));
+/// ```
+///
+/// [`NegativeDegreeDayCost`]: ../enum.EstimationError.html#variant.NegativeDegreeDayCost
+/// [`BalanceTemperatureAtEdge`]: ../enum.EstimationError.html#variant.BalanceTemperatureAtEdge
+#[derive(Debug, Clone)]
+pub struct DegreeDays {
+    temperatures: DailyTemperatures,
+    balance_temperatures: Vec<f64>,
+}
+
+impl DegreeDays {
+    /// Creates a model that tries balance temperatures from 50 to 75 degrees
+    /// Fahrenheit
+    pub fn new(temperatures: DailyTemperatures) -> Self {
+        DegreeDays {
+            temperatures,
+            balance_temperatures: (50..=75).map(f64::from).collect(),
+        }
+    }
+
+    /// Sets the balance temperatures to try, in the same units as the daily
+    /// temperatures
+    pub fn with_balance_temperatures<I>(mut self, balance_temperatures: I) -> Self
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        self.balance_temperatures = balance_temperatures.into_iter().collect();
+        self
+    }
+
+    fn estimate_with_balance(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+        balance_temperature: f64,
+    ) -> Result<Estimate, Error> {
+        let mut design = Design::new(bill, notes, history)?;
+        let terms: [(&str, DegreeDaySum); 2] = [
+            ("HDD", DailyTemperatures::heating_degree_days),
+            ("CDD", DailyTemperatures::cooling_degree_days),
+        ];
+        for (name, degree_days) in terms.iter() {
+            let column = history
+                .iter()
+                .map(|(bill, _, _)| {
+                    degree_days(&self.temperatures, bill.usage_period(), balance_temperature)
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            // a term that is zero for every bill can't be fit
+            if column.iter().all(|dd| *dd == 0.0) {
+                continue;
+            }
+            design.names.push(String::from(*name));
+            design.columns.push(column);
+            design.current.push(degree_days(
+                &self.temperatures,
+                bill.usage_period(),
+                balance_temperature,
+            )?);
+            design.shared.push(true);
+        }
+        let mut estimate = design.fit(false, None)?;
+        if let Some(coefficient) = estimate
+            .coefficients
+            .iter()
+            .find(|c| (c.name == "HDD" || c.name == "CDD") && c.value < 0.0)
+        {
+            return Err(Error::Estimation(EstimationError::NegativeDegreeDayCost {
+                term: coefficient.name.clone(),
+                cost_per_degree_day: coefficient.value,
+            }));
+        }
+        estimate
+            .parameters
+            .push((String::from("balance temperature"), balance_temperature));
+        Ok(estimate)
+    }
+}
+
+impl EstimationModel for DegreeDays {
+    fn name(&self) -> String {
+        String::from("heating and cooling degree days")
+    }
+
+    fn estimate(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error> {
+        let mut best: Option<(f64, Estimate)> = None;
+        let mut last_error = None;
+        for balance_temperature in &self.balance_temperatures {
+            match self.estimate_with_balance(bill, notes, history, *balance_temperature) {
+                Ok(estimate) => {
+                    let fit = adjusted_rsquared(&estimate, history.len());
+                    let better = match &best {
+                        Some((_, best)) => fit > adjusted_rsquared(best, history.len()),
+                        None => true,
+                    };
+                    if better {
+                        best = Some((*balance_temperature, estimate));
+                    }
+                }
+                Err(error @ Error::Estimation(EstimationError::MissingTemperature { .. })) => {
+                    return Err(error)
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+        let (balance_temperature, best) = best.ok_or_else(|| {
+            last_error.unwrap_or_else(|| {
+                Error::Estimation(EstimationError::Regression(String::from(
+                    "no balance temperatures to try",
+                )))
+            })
+        })?;
+        let (lowest, highest) = self
+            .balance_temperatures
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), t| {
+                (low.min(*t), high.max(*t))
+            });
+        if lowest < highest && (balance_temperature == lowest || balance_temperature == highest) {
+            return Err(Error::Estimation(
+                EstimationError::BalanceTemperatureAtEdge {
+                    balance_temperature,
+                },
+            ));
+        }
+        Ok(best)
+    }
+}
+
+/// R² adjusted for the number of regressors, so that a balance temperature
+/// with both heating and cooling degree days doesn't win just by having more
+/// to fit with
+///
+/// The fit leaves at least one degree of freedom to adjust by, since least
+/// squares needs more observations than coefficients.
+fn adjusted_rsquared(estimate: &Estimate, observations: usize) -> Option<f64> {
+    let regressors = estimate.coefficients.len().saturating_sub(1);
+    let (n, regressors) = (observations as f64, regressors as f64);
+    estimate
+        .rsquared
+        .map(|rsquared| 1.0 - (1.0 - rsquared) * (n - 1.0) / (n - regressors - 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::NONE;
+    use steel_cent::{currency::USD, Money};
+
+    /// A year of temperatures going from 35 in winter to 85 in summer
+    fn year_of_temperatures() -> DailyTemperatures {
+        DateInterval::new((2020, 1, 1), (2020, 12, 31))
+            .unwrap()
+            .days()
+            .enumerate()
+            .map(|(day, date)| {
+                let angle = (day as f64 - 15.0) / 366.0 * 2.0 * std::f64::consts::PI;
+                (date, (60.0 - 25.0 * angle.cos()).round())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_balance_temperature() {
+        let temperatures = year_of_temperatures();
+        let bills = (1..=12)
+            .map(|month| {
+                let period = DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap();
+                let occupancy = 28 * (1 + month % 3);
+                let hdd = temperatures.heating_degree_days(period, 62.0).unwrap();
+                let cdd = temperatures.cooling_degree_days(period, 62.0).unwrap();
+                let cost = 20_00.0 + 50.0 * occupancy as f64 + 10.0 * hdd + 20.0 * cdd;
+                (
+                    Bill::new(Money::of_minor(USD, cost as i64), period),
+                    occupancy,
+                )
+            })
+            .collect::<Vec<_>>();
+        let (current, occupancy) = &bills[11];
+        let history = bills[..11]
+            .iter()
+            .map(|(bill, oc)| (bill, *oc, &NONE))
+            .collect::<Vec<_>>();
+        let estimate = DegreeDays::new(temperatures.clone())
+            .estimate(current, (*occupancy, &NONE), &history)
+            .unwrap();
+        assert_eq!(
+            estimate.parameters,
+            vec![(String::from("balance temperature"), 62.0)]
+        );
+        let hdd = temperatures
+            .heating_degree_days(current.usage_period(), 62.0)
+            .unwrap();
+        assert!((estimate.shared_cost - (20_00.0 + 10.0 * hdd)).abs() < 1.0);
+
+        let too_cold = DegreeDays::new(temperatures)
+            .with_balance_temperatures((50..=60).map(f64::from))
+            .estimate(current, (*occupancy, &NONE), &history);
+        assert_eq!(
+            too_cold.err(),
+            Some(Error::Estimation(
+                EstimationError::BalanceTemperatureAtEdge {
+                    balance_temperature: 60.0
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn degree_days_must_cost_more() {
+        let temperatures = year_of_temperatures();
+        // heating below 90 degrees, which is every day, and a little noise
+        let bills = (1..=12)
+            .map(|month| {
+                let period = DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap();
+                let occupancy = 28 * (1 + month % 3);
+                let hdd = temperatures.heating_degree_days(period, 90.0).unwrap();
+                let noise = if month % 2 == 0 { 30.0 } else { -30.0 };
+                let cost = 20_00.0 + 50.0 * occupancy as f64 + 5.0 * hdd + noise;
+                (
+                    Bill::new(Money::of_minor(USD, cost as i64), period),
+                    occupancy,
+                )
+            })
+            .collect::<Vec<_>>();
+        let (current, occupancy) = &bills[11];
+        let history = bills[..11]
+            .iter()
+            .map(|(bill, oc)| (bill, *oc, &NONE))
+            .collect::<Vec<_>>();
+        let model = DegreeDays::new(temperatures).with_balance_temperatures(vec![60.0, 90.0, 95.0]);
+        // 60 degrees splits the same heating into heating and cooling degree
+        // days, which fits the noise with cooling that lowers the bill
+        assert!(matches!(
+            model.estimate_with_balance(current, (*occupancy, &NONE), &history, 60.0),
+            Err(Error::Estimation(EstimationError::NegativeDegreeDayCost { term, .. }))
+                if term == "CDD"
+        ));
+        let estimate = model
+            .estimate(current, (*occupancy, &NONE), &history)
+            .unwrap();
+        assert_eq!(
+            estimate.parameters,
+            vec![(String::from("balance temperature"), 90.0)]
+        );
+    }
+
+    #[test]
+    fn missing_temperature() {
+        let temperatures = DailyTemperatures::default();
+        let period = DateInterval::new((2020, 1, 1), (2020, 1, 2)).unwrap();
+        assert_eq!(
+            temperatures.heating_degree_days(period, 65.0),
+            Err(Error::Estimation(EstimationError::MissingTemperature {
+                date: period.start()
+            }))
+        );
+    }
+}
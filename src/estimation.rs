@@ -0,0 +1,1057 @@
+use chrono::Datelike;
+use std::collections::BTreeMap;
+
+use super::bill::Bill;
+use super::interval::DateInterval;
+use super::regression::{regression_error, variance_inflation_factors, LeastSquares};
+use crate::{Error, EstimationError};
+
+/// The probability that a prediction interval should cover
+pub(crate) const PREDICTION_LEVEL: f64 = 0.95;
+
+/// Infers how much of a bill is shared from the history of earlier bills
+///
+/// A model only makes the estimate; whether the estimate is accepted is up
+/// to the [`EstimationConfig`] it is used with.
+///
+/// [`EstimationConfig`]: struct.EstimationConfig.html
+pub trait EstimationModel {
+    /// A short description of the model
+    fn name(&self) -> String;
+
+    /// Estimates the shared cost of `bill`
+    ///
+    /// `notes` are the occupancy (in person-days) and covariates for `bill`,
+    /// and each history entry is an earlier bill with its own notes.
+    fn estimate(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error>;
+
+    /// Whether the model copes with too little history on its own, so that
+    /// [`EstimationConfig::min_history`] doesn't apply to it
+    ///
+    /// [`EstimationConfig::min_history`]: struct.EstimationConfig.html#structfield.min_history
+    fn handles_short_history(&self) -> bool {
+        false
+    }
+}
+
+/// A measurement of something that drives the cost of a bill, such as a
+/// temperature index or a number of laundry loads
+///
+/// Like occupancy, it must be a total over the bill's usage period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Covariate {
+    /// Drives a cost that everyone shares evenly, like heating
+    Shared(f64),
+    /// Drives a cost that is split by responsibility, like laundry
+    Individual(f64),
+}
+
+impl Covariate {
+    pub fn value(self) -> f64 {
+        match self {
+            Covariate::Shared(value) | Covariate::Individual(value) => value,
+        }
+    }
+
+    fn is_shared(self) -> bool {
+        matches!(self, Covariate::Shared(_))
+    }
+}
+
+/// The covariates of a bill, by name
+///
+/// # Examples
+/// ```
+/// use roommates::sharing::{Covariate, Covariates};
+///
+/// let mut covariates = Covariates::new();
+/// covariates.insert(String::from("TI"), Covariate::Shared(1_250.0));
+/// covariates.insert(String::from("laundry loads"), Covariate::Individual(14.0));
+/// ```
+pub type Covariates = BTreeMap<String, Covariate>;
+
+/// A model's estimate of the shared cost of a bill, with diagnostics
+///
+/// Amounts are in minor units (e.g. cents) of the bill's currency. Models
+/// that don't compute some diagnostics can leave them at their defaults.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Estimate {
+    /// The estimated shared cost, which may be outside of the range from
+    /// zero to the amount due
+    pub shared_cost: f64,
+    /// The proportion of the variation in the history explained by the
+    /// model, if the model measures it
+    pub rsquared: Option<f64>,
+    /// The model's prediction of the bill's amount due, if it makes one
+    pub predicted: Option<f64>,
+    /// The fitted coefficients of the model
+    pub coefficients: Vec<Coefficient>,
+    /// The range that the shared cost falls in with 95% probability
+    pub prediction_interval: Option<(f64, f64)>,
+    /// The terms that add up to `shared_cost`, by name
+    pub contributions: Vec<(String, f64)>,
+    /// The usage periods of history bills that the model treated as outliers
+    pub outliers: Vec<DateInterval>,
+    /// Other parameters that the model chose, such as a balance temperature
+    pub parameters: Vec<(String, f64)>,
+    /// The variance inflation factor of each regressor, which is large when
+    /// the regressor varies together with the others (as occupancy and the
+    /// weather might)
+    pub variance_inflation: Vec<(String, f64)>,
+}
+
+impl Estimate {
+    /// The fitted cost (in minor units) of each person-day, if the model has
+    /// an occupancy coefficient
+    pub fn marginal_cost(&self) -> Option<f64> {
+        self.coefficients
+            .iter()
+            .find(|c| c.name == "Oc")
+            .map(|c| c.value)
+    }
+
+    /// The fitted cost (in minor units) of the individual covariates in
+    /// `covariates`, if the model has a coefficient for each of them
+    pub fn individual_cost(&self, covariates: &Covariates) -> Option<f64> {
+        covariates
+            .iter()
+            .filter(|(_, covariate)| matches!(covariate, Covariate::Individual(_)))
+            .map(|(name, covariate)| {
+                self.coefficients
+                    .iter()
+                    .find(|c| c.name == *name)
+                    .map(|c| c.value * covariate.value())
+            })
+            .sum()
+    }
+}
+
+/// A fitted coefficient with its standard error and the two-sided p-value
+/// for it being zero
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coefficient {
+    pub name: String,
+    pub value: f64,
+    pub standard_error: f64,
+    pub p_value: f64,
+}
+
+/// Fits `Y ~ Oc + covariates` by ordinary least squares, where `Y` is the
+/// amount due minus the fixed cost and `Oc` is occupancy
+///
+/// The shared cost is the fitted cost at zero occupancy and zero individual
+/// covariates, plus the bill's fixed cost. Can't tell apart a covariate and
+/// occupancy that always vary together, which [`EstimationConfig`] rejects.
+///
+/// [`EstimationConfig`]: struct.EstimationConfig.html
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OrdinaryLeastSquares;
+
+impl EstimationModel for OrdinaryLeastSquares {
+    fn name(&self) -> String {
+        String::from("ordinary least squares")
+    }
+
+    fn estimate(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error> {
+        least_squares(bill, notes, history, false, None)
+    }
+}
+
+/// Like [`OrdinaryLeastSquares`], but with cost, occupancy and covariates
+/// divided by the number of days in each bill's usage period
+///
+/// The fitted intercept is then a daily cost, which is scaled back up to the
+/// length of the current bill. Bills of different lengths no longer imply
+/// different fixed costs just because of their length.
+///
+/// [`OrdinaryLeastSquares`]: struct.OrdinaryLeastSquares.html
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PerDayLeastSquares;
+
+impl EstimationModel for PerDayLeastSquares {
+    fn name(&self) -> String {
+        String::from("ordinary least squares per day")
+    }
+
+    fn estimate(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error> {
+        least_squares(bill, notes, history, true, None)
+    }
+}
+
+/// Fits the same model as [`OrdinaryLeastSquares`] (or
+/// [`PerDayLeastSquares`] if `per_day` is set) by Huber M-estimation
+///
+/// Bills whose residual is more than `tuning` robust standard deviations from
+/// the fit count less towards it, so one catch-up bill can't skew the
+/// estimate. Bills weighted at less than half are reported as outliers. R² is
+/// computed with the final weights.
+///
+/// [`OrdinaryLeastSquares`]: struct.OrdinaryLeastSquares.html
+/// [`PerDayLeastSquares`]: struct.PerDayLeastSquares.html
+#[derive(Debug, Clone, Copy)]
+pub struct HuberLeastSquares {
+    pub tuning: f64,
+    pub per_day: bool,
+}
+
+impl Default for HuberLeastSquares {
+    fn default() -> Self {
+        HuberLeastSquares {
+            // 95% as efficient as least squares when there are no outliers
+            tuning: 1.345,
+            per_day: false,
+        }
+    }
+}
+
+impl EstimationModel for HuberLeastSquares {
+    fn name(&self) -> String {
+        if self.per_day {
+            String::from("Huber regression per day")
+        } else {
+            String::from("Huber regression")
+        }
+    }
+
+    fn estimate(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error> {
+        least_squares(bill, notes, history, self.per_day, Some(self.tuning))
+    }
+}
+
+/// Fits the same model as [`OrdinaryLeastSquares`] (or
+/// [`PerDayLeastSquares`] if `per_day` is set) by weighted least squares,
+/// with older bills counting less
+///
+/// A bill counts half as much for every `half_life_days` between the end of
+/// its usage period and the end of the current bill's, so that bills from
+/// before a rate change fade out. R² is computed with the weights. To drop
+/// old bills altogether, set [`EstimationConfig::tariff_change`].
+///
+/// Returns an [`InvalidHalfLife`] error unless `half_life_days` is finite
+/// and positive.
+///
+/// [`OrdinaryLeastSquares`]: struct.OrdinaryLeastSquares.html
+/// [`PerDayLeastSquares`]: struct.PerDayLeastSquares.html
+/// [`EstimationConfig::tariff_change`]: struct.EstimationConfig.html#structfield.tariff_change
+/// [`InvalidHalfLife`]: ../enum.EstimationError.html#variant.InvalidHalfLife
+#[derive(Debug, Clone, Copy)]
+pub struct WeightedLeastSquares {
+    pub half_life_days: f64,
+    pub per_day: bool,
+}
+
+impl Default for WeightedLeastSquares {
+    fn default() -> Self {
+        WeightedLeastSquares {
+            half_life_days: 365.0,
+            per_day: false,
+        }
+    }
+}
+
+impl EstimationModel for WeightedLeastSquares {
+    fn name(&self) -> String {
+        format!(
+            "least squares weighted with a {} day half-life{}",
+            self.half_life_days,
+            if self.per_day { " per day" } else { "" }
+        )
+    }
+
+    fn estimate(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error> {
+        let mut design = Design::new(bill, notes, history)?;
+        design.weight_by_age(self.half_life_days)?;
+        design.fit(self.per_day, None)
+    }
+}
+
+/// Like [`PerDayLeastSquares`], but with a daily zero-occupancy cost for
+/// each month of the year
+///
+/// Each bill's days are counted by the month they fall in, so a winter bill
+/// is compared with earlier winters instead of with the summer. Needs a few
+/// years of history: every month of the current bill must appear in the
+/// history, and there must be more history bills than months in it.
+///
+/// [`PerDayLeastSquares`]: struct.PerDayLeastSquares.html
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeasonalLeastSquares;
+
+impl EstimationModel for SeasonalLeastSquares {
+    fn name(&self) -> String {
+        String::from("seasonal least squares per day")
+    }
+
+    fn estimate(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error> {
+        let mut design = Design::new(bill, notes, history)?;
+        let history_days = history
+            .iter()
+            .map(|(bill, _, _)| days_by_month(bill.usage_period()))
+            .collect::<Vec<_>>();
+        let current_days = days_by_month(bill.usage_period());
+        let months = (0..12)
+            .filter(|month| history_days.iter().any(|days| days[*month] > 0.0))
+            .collect::<Vec<_>>();
+        if let Some(missing) =
+            (0..12).find(|month| current_days[*month] > 0.0 && !months.contains(month))
+        {
+            return Err(regression_error(format!(
+                "no history bills from {}",
+                MONTHS[missing]
+            )));
+        }
+        // the first month is the baseline that the intercept measures, since
+        // per day the months always add up to one
+        for month in months.into_iter().skip(1) {
+            design.names.push(String::from(MONTHS[month]));
+            design
+                .columns
+                .push(history_days.iter().map(|days| days[month]).collect());
+            design.current.push(current_days[month]);
+            design.shared.push(true);
+        }
+        design.fit(true, None)
+    }
+}
+
+/// Blends another model's estimate with a prior guess of the shared fraction
+/// of each bill, for households with little history
+///
+/// The prior counts as `prior_bills` bills of history, so with `n` bills the
+/// estimate is `n / (n + prior_bills)` of the way from the prior to the
+/// other model's estimate. Until there is enough history to fit the other
+/// model (it returns an [`InsufficientHistory`], [`Regression`] or
+/// [`Collinear`] error), the estimate is the prior; any other error, such
+/// as a covariate that is shared for some bills and individual for others,
+/// is returned. The prior shares the bill's fixed cost and the prior
+/// fraction of the rest. When the estimate is the prior because the other
+/// model failed, the prior's contribution is labeled with the reason.
+///
+/// The blend predicts nothing and has no R², so only the range of the
+/// estimate is checked, and since it handles short histories itself,
+/// [`EstimationConfig::min_history`] doesn't apply to it.
+///
+/// # Examples
+/// ```
+/// use roommates::sharing::{HuberLeastSquares, Shrinkage};
+///
+/// // 60% of the bill is shared, until several bills show otherwise
+/// let model = Shrinkage::new(0.6)
+///     .with_prior_bills(6.0)
+///     .with_model(Box::new(HuberLeastSquares::default()));
+/// ```
+///
+/// [`InsufficientHistory`]: ../enum.EstimationError.html#variant.InsufficientHistory
+/// [`Regression`]: ../enum.EstimationError.html#variant.Regression
+/// [`Collinear`]: ../enum.EstimationError.html#variant.Collinear
+/// [`EstimationConfig::min_history`]: struct.EstimationConfig.html#structfield.min_history
+pub struct Shrinkage {
+    prior_shared_fraction: f64,
+    prior_bills: f64,
+    model: Box<dyn EstimationModel>,
+}
+
+impl Shrinkage {
+    /// Shrinks [`PerDayLeastSquares`] towards `prior_shared_fraction` (which
+    /// is clamped between zero and one) with the weight of four bills
+    ///
+    /// [`PerDayLeastSquares`]: struct.PerDayLeastSquares.html
+    pub fn new(prior_shared_fraction: f64) -> Self {
+        Shrinkage {
+            // NaN counts as zero
+            prior_shared_fraction: if prior_shared_fraction > 0.0 {
+                prior_shared_fraction.min(1.0)
+            } else {
+                0.0
+            },
+            prior_bills: 4.0,
+            model: Box::new(PerDayLeastSquares),
+        }
+    }
+
+    /// Sets how many bills of history the prior counts as
+    pub fn with_prior_bills(mut self, prior_bills: f64) -> Self {
+        self.prior_bills = prior_bills.max(0.0);
+        self
+    }
+
+    /// Sets the model whose estimate is shrunk
+    pub fn with_model(mut self, model: Box<dyn EstimationModel>) -> Self {
+        self.model = model;
+        self
+    }
+}
+
+impl EstimationModel for Shrinkage {
+    fn name(&self) -> String {
+        format!(
+            "{} shrunk towards {}% shared",
+            self.model.name(),
+            self.prior_shared_fraction * 100.0
+        )
+    }
+
+    fn handles_short_history(&self) -> bool {
+        true
+    }
+
+    fn estimate(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error> {
+        let fixed_cost = bill.fixed_cost().minor_amount() as f64;
+        let variable_cost = bill.amount_due().minor_amount() as f64 - fixed_cost;
+        let prior = fixed_cost + self.prior_shared_fraction * variable_cost;
+        // mixed covariates are a mistake in the notes, not too little history
+        covariate_columns(
+            notes.1,
+            history.iter().map(|(_, _, covariates)| *covariates),
+        )?;
+        let failed = |reason: String| {
+            let label = format!("prior ({} failed: {})", self.model.name(), reason);
+            (label, 0.0, Estimate::default())
+        };
+        let (prior_label, data_weight, data) = match self.model.estimate(bill, notes, history) {
+            Ok(data) if data.shared_cost.is_finite() => {
+                let bills = history.len() as f64;
+                let data_weight = if bills + self.prior_bills > 0.0 {
+                    bills / (bills + self.prior_bills)
+                } else {
+                    1.0
+                };
+                (String::from("prior"), data_weight, data)
+            }
+            Ok(data) => failed(format!("estimated a shared cost of {}", data.shared_cost)),
+            Err(
+                error @ Error::Estimation(
+                    EstimationError::InsufficientHistory { .. }
+                    | EstimationError::Regression(_)
+                    | EstimationError::Collinear { .. },
+                ),
+            ) => failed(error.to_string()),
+            Err(error) => return Err(error),
+        };
+        let mut contributions = vec![(prior_label, (1.0 - data_weight) * prior)];
+        contributions.extend(
+            data.contributions
+                .into_iter()
+                .map(|(name, amount)| (name, data_weight * amount)),
+        );
+        Ok(Estimate {
+            shared_cost: (1.0 - data_weight) * prior + data_weight * data.shared_cost,
+            coefficients: data.coefficients,
+            contributions,
+            outliers: data.outliers,
+            parameters: vec![(String::from("weight of the data"), data_weight)],
+            ..Estimate::default()
+        })
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// The number of days in `period` in each month of the year
+fn days_by_month(period: DateInterval) -> [f64; 12] {
+    let mut days = [0.0; 12];
+    for date in period.days() {
+        days[date.month0() as usize] += 1.0;
+    }
+    days
+}
+
+/// Fits `Y ~ Oc + covariates`, by Huber M-estimation if `huber_tuning` is
+/// given
+fn least_squares(
+    bill: &Bill,
+    notes: (u32, &Covariates),
+    history: &[(&Bill, u32, &Covariates)],
+    per_day: bool,
+    huber_tuning: Option<f64>,
+) -> Result<Estimate, Error> {
+    Design::new(bill, notes, history)?.fit(per_day, huber_tuning)
+}
+
+/// A linear model of the usage-dependent cost (`Y`) of the history bills
+///
+/// Every regressor must be a total over a bill's usage period (like
+/// person-days), so that it can be divided by the length of the period.
+pub(crate) struct Design<'a> {
+    pub(crate) bill: &'a Bill,
+    pub(crate) history: Vec<&'a Bill>,
+    pub(crate) y: Vec<f64>,
+    pub(crate) names: Vec<String>,
+    /// One column per regressor, with one value per history bill
+    pub(crate) columns: Vec<Vec<f64>>,
+    /// The value of each regressor for the current bill
+    pub(crate) current: Vec<f64>,
+    /// Whether each regressor's contribution is part of the shared cost
+    /// (like the weather) or not (like occupancy)
+    pub(crate) shared: Vec<bool>,
+    /// How much each history bill counts towards the fit, if not equally
+    pub(crate) weights: Option<Vec<f64>>,
+    /// What the current bill shares besides the fitted cost at zero occupancy
+    pub(crate) fixed_cost: f64,
+}
+
+impl<'a> Design<'a> {
+    /// Regresses `Y` (the amount due minus the fixed cost) on occupancy and
+    /// each covariate recorded for some history bill, as in
+    /// `covariate_columns`
+    pub(crate) fn new(
+        bill: &'a Bill,
+        (occupancy, covariates): (u32, &Covariates),
+        history: &[(&'a Bill, u32, &Covariates)],
+    ) -> Result<Self, Error> {
+        let mut design = Design {
+            bill,
+            history: history.iter().map(|(bill, _, _)| *bill).collect(),
+            y: history
+                .iter()
+                .map(|(bill, _, _)| {
+                    (bill.amount_due().minor_amount() - bill.fixed_cost().minor_amount()) as f64
+                })
+                .collect(),
+            names: vec![String::from("Oc")],
+            columns: vec![history.iter().map(|(_, oc, _)| *oc as f64).collect()],
+            current: vec![occupancy as f64],
+            shared: vec![false],
+            weights: None,
+            fixed_cost: bill.fixed_cost().minor_amount() as f64,
+        };
+        for (name, shared, column) in covariate_columns(
+            covariates,
+            history.iter().map(|(_, _, covariates)| *covariates),
+        )? {
+            design
+                .current
+                .push(covariates.get(&name).map_or(0.0, |c| c.value()));
+            design.names.push(name);
+            design.columns.push(column);
+            design.shared.push(shared);
+        }
+        Ok(design)
+    }
+
+    /// Weights each history bill by half for every `half_life_days` between
+    /// the ends of its usage period and the current bill's
+    pub(crate) fn weight_by_age(&mut self, half_life_days: f64) -> Result<(), Error> {
+        if !(half_life_days.is_finite() && half_life_days > 0.0) {
+            return Err(Error::Estimation(EstimationError::InvalidHalfLife {
+                half_life_days,
+            }));
+        }
+        let end = self.bill.usage_period().end();
+        self.weights = Some(
+            self.history
+                .iter()
+                .map(|bill| {
+                    let age = (end - bill.usage_period().end()).num_days().max(0) as f64;
+                    0.5f64.powf(age / half_life_days)
+                })
+                .collect(),
+        );
+        Ok(())
+    }
+
+    /// Fits the model, optionally per day and by Huber M-estimation, and
+    /// estimates the current bill's shared cost as its fixed cost plus the
+    /// intercept and the shared regressors' contributions
+    pub(crate) fn fit(self, per_day: bool, huber_tuning: Option<f64>) -> Result<Estimate, Error> {
+        let days = |bill: &Bill| {
+            if per_day {
+                bill.usage_period().num_days() as f64
+            } else {
+                1.0
+            }
+        };
+        let mut y = self.y;
+        let mut columns = self.columns;
+        for (i, bill) in self.history.iter().enumerate() {
+            let days = days(bill);
+            y[i] /= days;
+            for column in columns.iter_mut() {
+                column[i] /= days;
+            }
+        }
+        // measured with the weights of the fit, so that bills that barely
+        // count can't tell regressors apart
+        let names = &self.names;
+        let given_weights = self.weights.as_deref();
+        let variance_inflation = |weights: Option<&[f64]>| {
+            names
+                .iter()
+                .zip(variance_inflation_factors(&columns, weights))
+                .filter_map(|(name, vif)| Some((name.clone(), vif?)))
+                .collect::<Vec<_>>()
+        };
+        let mut variance_inflation_factors = variance_inflation(given_weights);
+        if let Some((name, vif)) = variance_inflation_factors
+            .iter()
+            .find(|(_, vif)| vif.is_infinite())
+        {
+            return Err(Error::Estimation(EstimationError::Collinear {
+                regressor: name.clone(),
+                variance_inflation: *vif,
+            }));
+        }
+        let (fit, outliers) = match huber_tuning {
+            Some(tuning) => {
+                let (fit, weights) = LeastSquares::fit_huber(&y, &columns, tuning, given_weights)?;
+                let combined = weights
+                    .iter()
+                    .enumerate()
+                    .map(|(i, huber)| huber * given_weights.map_or(1.0, |w| w[i]))
+                    .collect::<Vec<_>>();
+                variance_inflation_factors = variance_inflation(Some(&combined));
+                let outliers = self
+                    .history
+                    .iter()
+                    .zip(weights)
+                    .filter(|(_, weight)| *weight < 0.5)
+                    .map(|(bill, _)| bill.usage_period())
+                    .collect();
+                (fit, outliers)
+            }
+            None => (LeastSquares::fit(&y, &columns, given_weights)?, vec![]),
+        };
+        let days = days(self.bill);
+        let current = self.current.iter().map(|x| x / days).collect::<Vec<_>>();
+        let zero_occupancy = current
+            .iter()
+            .zip(&self.shared)
+            .map(|(x, shared)| if *shared { *x } else { 0.0 })
+            .collect::<Vec<_>>();
+        let predicted = fit.predict(&current) * days;
+        let (low, high) = fit.prediction_interval(&zero_occupancy, PREDICTION_LEVEL);
+        let fixed_cost = self.fixed_cost;
+        let mut contributions = vec![(String::from("intercept"), fit.coefficients()[0] * days)];
+        for (i, name) in self.names.iter().enumerate() {
+            if self.shared[i] {
+                let contribution = fit.coefficients()[i + 1] * zero_occupancy[i] * days;
+                contributions.push((name.clone(), contribution));
+            }
+        }
+        contributions.push((String::from("fixed cost"), fixed_cost));
+        Ok(Estimate {
+            shared_cost: contributions.iter().map(|(_, amount)| amount).sum(),
+            rsquared: Some(fit.rsquared()),
+            predicted: Some(predicted),
+            coefficients: std::iter::once(String::from("intercept"))
+                .chain(self.names)
+                .enumerate()
+                .map(|(i, name)| Coefficient {
+                    name,
+                    value: fit.coefficients()[i],
+                    standard_error: fit.standard_errors()[i],
+                    p_value: fit.p_values()[i],
+                })
+                .collect(),
+            prediction_interval: Some((low * days + fixed_cost, high * days + fixed_cost)),
+            contributions,
+            outliers,
+            parameters: vec![],
+            variance_inflation: variance_inflation_factors,
+        })
+    }
+}
+
+/// The name, whether it is shared, and the column of each covariate recorded
+/// for some history bill
+///
+/// A bill without a covariate counts as zero for it. Covariates that are
+/// zero for every history bill can't be fit and are left out. Returns a
+/// [`Regression`] error if a covariate is shared for some bills (including
+/// the current one) and individual for others.
+///
+/// [`Regression`]: ../enum.EstimationError.html#variant.Regression
+pub(crate) fn covariate_columns<'c, I>(
+    current: &'c Covariates,
+    history: I,
+) -> Result<Vec<(String, bool, Vec<f64>)>, Error>
+where
+    I: IntoIterator<Item = &'c Covariates>,
+{
+    let history = history.into_iter().collect::<Vec<_>>();
+    let mut kinds = BTreeMap::new();
+    for (name, covariate) in std::iter::once(current)
+        .chain(history.iter().copied())
+        .flatten()
+    {
+        if *kinds.entry(name).or_insert_with(|| covariate.is_shared()) != covariate.is_shared() {
+            return Err(regression_error(format!(
+                "{} is shared for some bills and individual for others",
+                name
+            )));
+        }
+    }
+    Ok(kinds
+        .into_iter()
+        .map(|(name, shared)| {
+            let column = history
+                .iter()
+                .map(|covariates| covariates.get(name).map_or(0.0, |c| c.value()))
+                .collect::<Vec<_>>();
+            (name.clone(), shared, column)
+        })
+        .filter(|(_, _, column)| column.iter().any(|value| *value != 0.0))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::DateInterval;
+    use crate::tariff::MeteredUsage;
+    use crate::test_fixtures::NONE;
+    use chrono::NaiveDate;
+    use steel_cent::{currency::USD, Money};
+
+    fn new_bill(minor: i64) -> Bill {
+        Bill::new(
+            Money::of_minor(USD, minor),
+            DateInterval::from_strs("01/01/2020", "02/01/2020").unwrap(),
+        )
+    }
+
+    fn covariates(pairs: &[(&str, Covariate)]) -> Covariates {
+        pairs
+            .iter()
+            .map(|(name, covariate)| (String::from(*name), *covariate))
+            .collect()
+    }
+
+    #[test]
+    fn design_from_covariates() {
+        let bills = [
+            (new_bill(20_00), 1),
+            (new_bill(30_00), 2),
+            (new_bill(40_00), 3),
+        ];
+        let notes = [
+            covariates(&[
+                ("TI", Covariate::Shared(5.0)),
+                ("AC", Covariate::Shared(0.0)),
+            ]),
+            covariates(&[("laundry", Covariate::Individual(2.0))]),
+            covariates(&[("TI", Covariate::Shared(7.0))]),
+        ];
+        let history = bills
+            .iter()
+            .zip(&notes)
+            .map(|((b, oc), covariates)| (b, *oc, covariates))
+            .collect::<Vec<_>>();
+        let current = covariates(&[("TI", Covariate::Shared(6.0))]);
+        let design = Design::new(&bills[0].0, (4, &current), &history).unwrap();
+        assert_eq!(design.y, vec![2000.0, 3000.0, 4000.0]);
+        assert_eq!(design.names, vec!["Oc", "TI", "laundry"]);
+        assert_eq!(
+            design.columns,
+            vec![
+                vec![1.0, 2.0, 3.0],
+                vec![5.0, 0.0, 7.0],
+                vec![0.0, 2.0, 0.0]
+            ]
+        );
+        assert_eq!(design.current, vec![4.0, 6.0, 0.0]);
+        assert_eq!(design.shared, vec![false, true, false]);
+        let mixed = covariates(&[("TI", Covariate::Individual(6.0))]);
+        assert!(matches!(
+            Design::new(&bills[0].0, (4, &mixed), &history),
+            Err(Error::Estimation(EstimationError::Regression(_)))
+        ));
+    }
+
+    #[test]
+    fn individual_covariate_is_not_shared() {
+        // $10 fixed, $5 per person-day and $2 per load of laundry
+        let bills = [(1, 3.0), (2, 1.0), (3, 4.0), (4, 1.0), (5, 5.0), (6, 9.0)]
+            .iter()
+            .map(|(oc, loads)| {
+                let cost = 10_00 + 5_00 * oc + 2_00 * *loads as i64;
+                let notes = covariates(&[("laundry", Covariate::Individual(*loads))]);
+                (new_bill(cost), *oc as u32, notes)
+            })
+            .collect::<Vec<_>>();
+        let history = bills
+            .iter()
+            .map(|(b, oc, covariates)| (b, *oc, covariates))
+            .collect::<Vec<_>>();
+        let current = covariates(&[("laundry", Covariate::Individual(4.0))]);
+        let estimate = OrdinaryLeastSquares
+            .estimate(&new_bill(28_00), (2, &current), &history)
+            .unwrap();
+        assert!((estimate.shared_cost - 10_00.0).abs() < 1.0);
+        assert!((estimate.predicted.unwrap() - 28_00.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn least_squares_diagnostics() {
+        let bills = (0..4)
+            .map(|oc| new_bill(10_00 + 10_00 * oc))
+            .collect::<Vec<_>>();
+        let history = bills
+            .iter()
+            .zip(0..)
+            .map(|(b, oc)| (b, oc, &NONE))
+            .collect::<Vec<_>>();
+        let estimate = OrdinaryLeastSquares
+            .estimate(&new_bill(50_00), (4, &NONE), &history)
+            .unwrap();
+        assert!((estimate.shared_cost - 10_00.0).abs() < 1.0);
+        assert!((estimate.predicted.unwrap() - 50_00.0).abs() < 1.0);
+        assert!(estimate.rsquared.unwrap() > 0.99);
+    }
+
+    #[test]
+    fn bill_length_does_not_change_daily_fixed_cost() {
+        // $1 a day fixed plus $0.50 per person-day
+        let bill = |start: &str, end: &str, people: u32| {
+            let period = DateInterval::from_strs(start, end).unwrap();
+            let days = period.num_days();
+            let cost = 1_00 * days as i64 + 50 * (people * days) as i64;
+            (Bill::new(Money::of_minor(USD, cost), period), people * days)
+        };
+        let bills = [
+            bill("01/01/2020", "01/25/2020", 1),
+            bill("01/26/2020", "02/27/2020", 2),
+            bill("02/28/2020", "03/27/2020", 3),
+            bill("03/28/2020", "04/30/2020", 1),
+            bill("05/01/2020", "05/25/2020", 2),
+        ];
+        let history = bills
+            .iter()
+            .map(|(b, oc)| (b, *oc, &NONE))
+            .collect::<Vec<_>>();
+        let (current, oc) = bill("06/01/2020", "06/10/2020", 2);
+        let estimate = PerDayLeastSquares
+            .estimate(&current, (oc, &NONE), &history)
+            .unwrap();
+        assert!((estimate.shared_cost - 10_00.0).abs() < 1.0);
+        assert!((estimate.predicted.unwrap() - 20_00.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn huber_flags_catch_up_bill() {
+        let bills = [10_10, 19_90, 30_05, 39_95, 50_00, 16_000, 70_10]
+            .iter()
+            .enumerate()
+            .map(|(month, amount)| {
+                let month = month as u32 + 1;
+                Bill::new(
+                    Money::of_minor(USD, *amount),
+                    DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let history = bills
+            .iter()
+            .zip(0..)
+            .map(|(b, oc)| (b, oc, &NONE))
+            .collect::<Vec<_>>();
+        let current = new_bill(80_00);
+        let ols = OrdinaryLeastSquares
+            .estimate(&current, (7, &NONE), &history)
+            .unwrap();
+        let huber = HuberLeastSquares::default()
+            .estimate(&current, (7, &NONE), &history)
+            .unwrap();
+        assert!(ols.outliers.is_empty());
+        assert!(ols.rsquared.unwrap() < 0.8);
+        assert_eq!(huber.outliers, vec![bills[5].usage_period()]);
+        assert!(huber.rsquared.unwrap() > 0.99);
+        assert!((huber.shared_cost - 10_00.0).abs() < 50.0);
+    }
+
+    #[test]
+    fn seasonal_compares_same_months() {
+        // a daily shared cost that peaks in winter, plus $0.30 per person-day
+        let daily = |month: u32| 1_00 + 40 * (month as i64 - 7).abs();
+        let month_bill = |year: i32, month: u32, people: u32| {
+            let next = match month {
+                12 => NaiveDate::from_ymd_opt(year + 1, 1, 1),
+                _ => NaiveDate::from_ymd_opt(year, month + 1, 1),
+            };
+            let end = next.unwrap().pred_opt().unwrap();
+            let period = DateInterval::new((year, month, 1), (year, month, end.day())).unwrap();
+            let days = period.num_days();
+            let cost = daily(month) * days as i64 + 30 * (people * days) as i64;
+            (Bill::new(Money::of_minor(USD, cost), period), people * days)
+        };
+        let bills = (2017..2020)
+            .flat_map(|year| (1..=12).map(move |month| (year, month)))
+            .map(|(year, month)| month_bill(year, month, 1 + (year as u32 + month) % 4))
+            .collect::<Vec<_>>();
+        let history = bills
+            .iter()
+            .map(|(b, oc)| (b, *oc, &NONE))
+            .collect::<Vec<_>>();
+        let (january, oc) = month_bill(2020, 1, 2);
+        let estimate = SeasonalLeastSquares
+            .estimate(&january, (oc, &NONE), &history)
+            .unwrap();
+        assert!((estimate.shared_cost - (daily(1) * 31) as f64).abs() < 1.0);
+        assert!(estimate.rsquared.unwrap() > 0.99);
+        let summers = history[5..8].to_vec();
+        assert!(matches!(
+            SeasonalLeastSquares.estimate(&january, (oc, &NONE), &summers),
+            Err(Error::Estimation(EstimationError::Regression(_)))
+        ));
+    }
+
+    #[test]
+    fn shrinkage_moves_from_prior_to_data() {
+        // the data says $10 of each bill is shared
+        let bills = (0..8)
+            .map(|oc| new_bill(10_00 + 10_00 * oc))
+            .collect::<Vec<_>>();
+        let history = bills
+            .iter()
+            .zip(0..)
+            .map(|(b, oc)| (b, oc, &NONE))
+            .collect::<Vec<_>>();
+        let current = new_bill(50_00);
+        let model = Shrinkage::new(0.6).with_model(Box::new(OrdinaryLeastSquares));
+        let shared = |bills: usize| {
+            model
+                .estimate(&current, (4, &NONE), &history[..bills])
+                .unwrap()
+                .shared_cost
+        };
+        assert_eq!(shared(0), 30_00.0);
+        assert_eq!(shared(2), 30_00.0);
+        let prior_label = |bills: usize| {
+            let estimate = model.estimate(&current, (4, &NONE), &history[..bills]);
+            estimate.unwrap().contributions[0].0.clone()
+        };
+        assert!(prior_label(2).starts_with("prior (ordinary least squares failed: Regression"));
+        assert_eq!(prior_label(4), "prior");
+        let blend = |weight: f64| (1.0 - weight) * 30_00.0 + weight * 10_00.0;
+        assert!((shared(4) - blend(0.5)).abs() < 1.0);
+        assert!((shared(8) - blend(8.0 / 12.0)).abs() < 1.0);
+        let unsure = Shrinkage::new(f64::NAN).with_prior_bills(-1.0);
+        assert_eq!(
+            unsure
+                .estimate(&current, (4, &NONE), &[])
+                .unwrap()
+                .shared_cost,
+            0.0
+        );
+
+        let laundry = covariates(&[("laundry", Covariate::Individual(2.0))]);
+        let mixed = covariates(&[("laundry", Covariate::Shared(2.0))]);
+        let history = bills
+            .iter()
+            .zip(0..)
+            .map(|(b, oc)| (b, oc, &laundry))
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            model.estimate(&current, (4, &mixed), &history[..2]),
+            Err(Error::Estimation(EstimationError::Regression(_)))
+        ));
+        let metered = Shrinkage::new(0.6).with_model(Box::new(MeteredUsage::default()));
+        assert_eq!(
+            metered.estimate(&current, (4, &NONE), &[]).err(),
+            Some(Error::Estimation(EstimationError::MissingTariff))
+        );
+    }
+
+    #[test]
+    fn weighting_favors_recent_bills() {
+        // the shared cost drops from $20 to $10 halfway through the year
+        let bills = (1..=12)
+            .map(|month| {
+                let people = 1 + month % 3;
+                let shared = if month <= 6 { 20_00 } else { 10_00 };
+                let period = DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap();
+                let bill = Bill::new(Money::of_minor(USD, shared + 10_00 * people as i64), period);
+                (bill, people)
+            })
+            .collect::<Vec<_>>();
+        let history = bills
+            .iter()
+            .map(|(b, oc)| (b, *oc, &NONE))
+            .collect::<Vec<_>>();
+        let current = Bill::new(
+            Money::of_minor(USD, 30_00),
+            DateInterval::new((2021, 1, 1), (2021, 1, 28)).unwrap(),
+        );
+        let ols = OrdinaryLeastSquares
+            .estimate(&current, (2, &NONE), &history)
+            .unwrap();
+        let weighted = WeightedLeastSquares {
+            half_life_days: 30.0,
+            per_day: false,
+        }
+        .estimate(&current, (2, &NONE), &history)
+        .unwrap();
+        assert!((ols.shared_cost - 15_00.0).abs() < 1_00.0);
+        assert!((weighted.shared_cost - 10_00.0).abs() < 1_00.0);
+    }
+
+    #[test]
+    fn half_life_must_be_positive() {
+        let history = [new_bill(20_00), new_bill(30_00), new_bill(40_00)];
+        let history = history
+            .iter()
+            .zip(1..)
+            .map(|(b, oc)| (b, oc, &NONE))
+            .collect::<Vec<_>>();
+        for half_life_days in [0.0, -30.0, f64::INFINITY] {
+            let model = WeightedLeastSquares {
+                half_life_days,
+                per_day: false,
+            };
+            assert_eq!(
+                model.estimate(&new_bill(50_00), (4, &NONE), &history).err(),
+                Some(Error::Estimation(EstimationError::InvalidHalfLife {
+                    half_life_days
+                }))
+            );
+        }
+    }
+}
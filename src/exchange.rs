@@ -0,0 +1,220 @@
+use chrono::naive::NaiveDate;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use steel_cent::{currency, currency::Currency};
+
+use super::bill::{Bill, SharedBill};
+use super::Error;
+
+/// A source of exchange rates between currencies
+pub trait ExchangeRateProvider {
+    /// Returns how many units of `to` one unit of `from` was worth on `date`
+    ///
+    /// Should return a [`MissingExchangeRate`] error if the rate is not known.
+    ///
+    /// [`MissingExchangeRate`]: ../enum.Error.html#variant.MissingExchangeRate
+    fn rate(&self, from: Currency, to: Currency, date: NaiveDate) -> Result<f64, Error>;
+}
+
+/// Dated exchange rates read from a CSV file
+///
+/// The file needs a header row followed by rows of
+/// `date,from,to,rate`, where the date is in `month/day/year` format, the
+/// currencies are ISO 4217 codes, and `rate` is how many units of `to` one
+/// unit of `from` is worth. The rate used for a given day is the most recent
+/// one on or before that day. If only the opposite direction is listed, its
+/// inverse is used.
+///
+/// # Examples
+/// ```
+/// use chrono::naive::NaiveDate;
+/// use roommates::sharing::{CsvExchangeRates, ExchangeRateProvider};
+/// use steel_cent::currency::{EUR, USD};
+///
+/// let rates = CsvExchangeRates::from_reader(
+///     "date,from,to,rate\n01/01/2020,EUR,USD,1.12\n02/01/2020,EUR,USD,1.10\n".as_bytes(),
+/// ).unwrap();
+/// assert_eq!(rates.rate(EUR, USD, NaiveDate::from_ymd_opt(2020, 1, 15).unwrap()), Ok(1.12));
+/// assert_eq!(rates.rate(EUR, USD, NaiveDate::from_ymd_opt(2020, 3, 1).unwrap()), Ok(1.10));
+/// assert_eq!(rates.rate(USD, EUR, NaiveDate::from_ymd_opt(2020, 3, 1).unwrap()), Ok(1.0 / 1.10));
+/// assert!(rates.rate(EUR, USD, NaiveDate::from_ymd_opt(2019, 12, 31).unwrap()).is_err());
+/// ```
+pub struct CsvExchangeRates {
+    rates: HashMap<(Currency, Currency), Vec<(NaiveDate, f64)>>,
+}
+
+impl CsvExchangeRates {
+    /// Reads exchange rates from the CSV file at `path`
+    ///
+    /// Returns an [`InvalidExchangeRates`] error if the file cannot be read
+    /// or has a malformed row.
+    ///
+    /// [`InvalidExchangeRates`]: ../enum.Error.html#variant.InvalidExchangeRates
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path).map_err(|e| Error::InvalidExchangeRates(e.to_string()))?;
+        CsvExchangeRates::from_reader(file)
+    }
+
+    /// Reads exchange rates in CSV format from `reader`
+    ///
+    /// Returns an [`InvalidExchangeRates`] error if a row is malformed.
+    ///
+    /// [`InvalidExchangeRates`]: ../enum.Error.html#variant.InvalidExchangeRates
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        let invalid = |message: String| Error::InvalidExchangeRates(message);
+        let mut rates: HashMap<_, Vec<_>> = HashMap::new();
+        for record in csv::Reader::from_reader(reader).records() {
+            let record = record.map_err(|e| invalid(e.to_string()))?;
+            if record.len() != 4 {
+                return Err(invalid(format!(
+                    "expected 4 columns, found {}",
+                    record.len()
+                )));
+            }
+            let date = NaiveDate::parse_from_str(&record[0], "%m/%d/%Y")
+                .map_err(|e| invalid(format!("{} ({})", e, &record[0])))?;
+            let from = currency::with_code(record[1].trim())
+                .ok_or_else(|| invalid(format!("unknown currency {}", &record[1])))?;
+            let to = currency::with_code(record[2].trim())
+                .ok_or_else(|| invalid(format!("unknown currency {}", &record[2])))?;
+            let rate = record[3]
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|rate| rate.is_finite() && *rate > 0.0)
+                .ok_or_else(|| invalid(format!("bad rate {}", &record[3])))?;
+            rates.entry((from, to)).or_default().push((date, rate));
+        }
+        for dated_rates in rates.values_mut() {
+            dated_rates.sort_by_key(|(date, _)| *date);
+        }
+        Ok(CsvExchangeRates { rates })
+    }
+
+    fn latest(&self, from: Currency, to: Currency, date: NaiveDate) -> Option<f64> {
+        self.rates
+            .get(&(from, to))?
+            .iter()
+            .rev()
+            .find(|(rate_date, _)| *rate_date <= date)
+            .map(|(_, rate)| *rate)
+    }
+}
+
+impl ExchangeRateProvider for CsvExchangeRates {
+    fn rate(&self, from: Currency, to: Currency, date: NaiveDate) -> Result<f64, Error> {
+        if from == to {
+            return Ok(1.0);
+        }
+        self.latest(from, to, date)
+            .or_else(|| self.latest(to, from, date).map(f64::recip))
+            .ok_or(Error::MissingExchangeRate { from, to, date })
+    }
+}
+
+/// Only knows that a currency is worth exactly itself
+///
+/// Any other conversion is a [`MixedCurrencies`] error.
+///
+/// [`MixedCurrencies`]: ../enum.Error.html#variant.MixedCurrencies
+pub(crate) struct SameCurrency;
+
+impl ExchangeRateProvider for SameCurrency {
+    fn rate(&self, from: Currency, to: Currency, _date: NaiveDate) -> Result<f64, Error> {
+        if from == to {
+            Ok(1.0)
+        } else {
+            Err(Error::MixedCurrencies)
+        }
+    }
+}
+
+impl SharedBill {
+    /// Converts the amount due and the shared amount into another currency
+    ///
+    /// The rate used is the one for the last day of the usage period, and is
+    /// returned along with the converted bill.
+    ///
+    /// # Examples
+    /// ```
+    /// use roommates::DateInterval;
+    /// use roommates::sharing::{Bill, CsvExchangeRates, SharedBill};
+    /// use steel_cent::{Money, currency::{EUR, USD}};
+    ///
+    /// let rates = CsvExchangeRates::from_reader(
+    ///     "date,from,to,rate\n01/01/2020,EUR,USD,1.10\n".as_bytes(),
+    /// ).unwrap();
+    /// let storage = SharedBill::new(
+    ///     Bill::new(
+    ///         Money::of_major(EUR, 40),
+    ///         DateInterval::from_strs("01/01/2020", "01/31/2020").unwrap(),
+    ///     ),
+    ///     Money::of_major(EUR, 10),
+    /// ).unwrap();
+    /// let (storage, rate) = storage.convert_to(USD, &rates).unwrap();
+    /// assert_eq!(rate, 1.10);
+    /// assert_eq!(storage.amount_due(), Money::of_major(USD, 44));
+    /// assert_eq!(storage.shared_amount(), Money::of_major(USD, 11));
+    /// ```
+    pub fn convert_to(
+        &self,
+        currency: Currency,
+        exchange_rates: &dyn ExchangeRateProvider,
+    ) -> Result<(SharedBill, f64), Error> {
+        let from = self.amount_due().currency;
+        let rate = exchange_rates.rate(from, currency, self.usage_period().end())?;
+        let bill = Bill::new_with_fixed_cost(
+            self.amount_due().convert_to(currency, rate),
+            self.usage_period(),
+            self.fixed_cost().convert_to(currency, rate),
+        )?;
+        let shared_amount = self.shared_amount().convert_to(currency, rate);
+        Ok((SharedBill::new(bill, shared_amount)?, rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use steel_cent::currency::{EUR, GBP, USD};
+
+    fn day(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2020, month, day).unwrap()
+    }
+
+    #[test]
+    fn malformed_rows() {
+        for csv in &[
+            "date,from,to,rate\n01/01/2020,EUR,USD\n",
+            "date,from,to,rate\n2020-01-01,EUR,USD,1.1\n",
+            "date,from,to,rate\n01/01/2020,EUR,XYZ,1.1\n",
+            "date,from,to,rate\n01/01/2020,EUR,USD,-1\n",
+        ] {
+            assert!(matches!(
+                CsvExchangeRates::from_reader(csv.as_bytes()),
+                Err(Error::InvalidExchangeRates(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn unsorted_rates() {
+        let rates = CsvExchangeRates::from_reader(
+            "date,from,to,rate\n03/01/2020,GBP,USD,1.3\n01/01/2020,GBP,USD,1.2\n".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(rates.rate(GBP, USD, day(2, 1)), Ok(1.2));
+        assert_eq!(rates.rate(GBP, USD, day(3, 1)), Ok(1.3));
+        assert_eq!(rates.rate(USD, USD, day(1, 1)), Ok(1.0));
+        assert_eq!(
+            rates.rate(EUR, USD, day(3, 1)),
+            Err(Error::MissingExchangeRate {
+                from: EUR,
+                to: USD,
+                date: day(3, 1)
+            })
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use chrono::{naive::NaiveDateTime, Duration};
+use num::{BigRational, Zero};
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::iter::FromIterator;
+use std::path::Path;
+
+use super::interval::{ResponsibilityInterval, ResponsibilityRecord};
+use super::roommate::{Roommate, RoommateGroup};
+use super::split::ResponsibilitySplit;
+use super::Error;
+
+/// The format of the times in hourly meter data
+const TIME_FORMAT: &str = "%m/%d/%Y %H:%M";
+
+/// Hourly usage and prices read from a smart meter's CSV export
+///
+/// The file needs a header row followed by rows of `start,usage,price`,
+/// where `start` is the beginning of the hour in `month/day/year hour:minute`
+/// format, `usage` is the metered usage (such as kWh) over the hour, and
+/// `price` is the time-of-use price of a unit of usage in minor units (such
+/// as cents). Both may be negative, as with net export from solar panels or
+/// negative wholesale prices, in which case the hour is a credit.
+///
+/// # Examples
+/// ```
+/// use roommates::sharing::HourlyMeterData;
+///
+/// let meter = HourlyMeterData::from_reader(
+///     "start,usage,price\n01/01/2020 17:00,2.0,30\n01/01/2020 18:00,1.5,12\n".as_bytes(),
+/// ).unwrap();
+/// assert_eq!(meter.cost(), 78.0);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HourlyMeterData {
+    hours: BTreeMap<NaiveDateTime, (f64, f64)>,
+}
+
+impl HourlyMeterData {
+    /// Reads hourly meter data from the CSV file at `path`
+    ///
+    /// Returns an [`InvalidMeterData`] error if the file cannot be read or
+    /// has a malformed row.
+    ///
+    /// [`InvalidMeterData`]: ../enum.Error.html#variant.InvalidMeterData
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path).map_err(|e| Error::InvalidMeterData(e.to_string()))?;
+        HourlyMeterData::from_reader(file)
+    }
+
+    /// Reads hourly meter data in CSV format from `reader`
+    ///
+    /// Returns an [`InvalidMeterData`] error if a row is malformed or an hour
+    /// is listed twice.
+    ///
+    /// [`InvalidMeterData`]: ../enum.Error.html#variant.InvalidMeterData
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        let invalid = |message: String| Error::InvalidMeterData(message);
+        let number = |field: &str| {
+            field
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+        };
+        let mut hours = BTreeMap::new();
+        for record in csv::Reader::from_reader(reader).records() {
+            let record = record.map_err(|e| invalid(e.to_string()))?;
+            if record.len() != 3 {
+                return Err(invalid(format!(
+                    "expected 3 columns, found {}",
+                    record.len()
+                )));
+            }
+            let start = NaiveDateTime::parse_from_str(record[0].trim(), TIME_FORMAT)
+                .map_err(|e| invalid(format!("{} ({})", e, &record[0])))?;
+            let usage =
+                number(&record[1]).ok_or_else(|| invalid(format!("bad usage {}", &record[1])))?;
+            let price =
+                number(&record[2]).ok_or_else(|| invalid(format!("bad price {}", &record[2])))?;
+            if hours.insert(start, (usage, price)).is_some() {
+                return Err(invalid(format!("hour {} listed twice", start)));
+            }
+        }
+        Ok(HourlyMeterData { hours })
+    }
+
+    /// The total cost (in minor units) of the usage over every hour
+    pub fn cost(&self) -> f64 {
+        self.hours
+            .values()
+            .map(|(usage, price)| usage * price)
+            .sum()
+    }
+}
+
+/// A continuous time that someone stayed in the house, to the minute
+///
+/// Like a [`ResponsibilityInterval`], but from a start time up to (and not
+/// including) an end time instead of over whole days.
+///
+/// [`ResponsibilityInterval`]: ../struct.ResponsibilityInterval.html
+#[derive(Clone)]
+pub struct HourlyResponsibilityInterval<'a> {
+    roommate: &'a Roommate,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    additional_people: u32,
+}
+
+impl<'a> HourlyResponsibilityInterval<'a> {
+    /// Creates a new `HourlyResponsibilityInterval`
+    ///
+    /// Returns a [`NegativeLengthInterval`] error if `end` is before `start`.
+    ///
+    /// [`NegativeLengthInterval`]: ../enum.Error.html#variant.NegativeLengthInterval
+    pub fn new(
+        roommate: &'a Roommate,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        additional_people: u32,
+    ) -> Result<Self, Error> {
+        if end < start {
+            return Err(Error::NegativeLengthInterval);
+        }
+        Ok(HourlyResponsibilityInterval {
+            roommate,
+            start,
+            end,
+            additional_people,
+        })
+    }
+
+    /// The person-hours of the interval within the hour starting at `hour`
+    fn occupancy_in_hour(&self, hour: NaiveDateTime) -> f64 {
+        let overlap = min(self.end, hour + Duration::hours(1)) - max(self.start, hour);
+        let hours = overlap.num_seconds().max(0) as f64 / 3600.0;
+        hours * (f64::from(self.additional_people) + 1.0)
+    }
+}
+
+impl<'a> From<&ResponsibilityInterval<'a>> for HourlyResponsibilityInterval<'a> {
+    /// Covers every hour of the interval's days
+    fn from(interval: &ResponsibilityInterval<'a>) -> Self {
+        let days = interval.interval();
+        HourlyResponsibilityInterval {
+            roommate: interval.roommate(),
+            start: days.start().and_hms_opt(0, 0, 0).unwrap(),
+            end: days.end().and_hms_opt(0, 0, 0).unwrap() + Duration::days(1),
+            additional_people: interval.additional_people(),
+        }
+    }
+}
+
+/// A record of who was in the house, to the minute
+///
+/// A day-level [`ResponsibilityRecord`] can be converted into one.
+///
+/// [`ResponsibilityRecord`]: ../struct.ResponsibilityRecord.html
+pub struct HourlyResponsibilityRecord<'a> {
+    intervals: Vec<HourlyResponsibilityInterval<'a>>,
+}
+
+impl HourlyResponsibilityRecord<'_> {
+    /// The person-hours that `roommate` is responsible for within the hour
+    /// starting at `hour`
+    pub fn roommate_occupancy_in_hour(&self, roommate: &Roommate, hour: NaiveDateTime) -> f64 {
+        self.intervals
+            .iter()
+            .filter(|interval| interval.roommate == roommate)
+            .map(|interval| interval.occupancy_in_hour(hour))
+            .sum()
+    }
+}
+
+impl<'a> FromIterator<HourlyResponsibilityInterval<'a>> for HourlyResponsibilityRecord<'a> {
+    fn from_iter<I: IntoIterator<Item = HourlyResponsibilityInterval<'a>>>(iter: I) -> Self {
+        HourlyResponsibilityRecord {
+            intervals: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<'a> From<&ResponsibilityRecord<'a>> for HourlyResponsibilityRecord<'a> {
+    fn from(record: &ResponsibilityRecord<'a>) -> Self {
+        record
+            .iter()
+            .map(HourlyResponsibilityInterval::from)
+            .collect()
+    }
+}
+
+/// Each roommate's share of the cost of each hour of meter data
+pub struct HourlyShares<'a> {
+    group: &'a RoommateGroup,
+    hours: Vec<HourShare<'a>>,
+}
+
+/// The cost of one hour (in minor units) and how much of it each roommate
+/// owes
+#[derive(Debug, Clone, PartialEq)]
+pub struct HourShare<'a> {
+    pub start: NaiveDateTime,
+    pub cost: f64,
+    pub shares: Vec<(&'a Roommate, f64)>,
+}
+
+impl RoommateGroup {
+    /// Splits the cost of each hour of meter data between the roommates in
+    /// proportion to the person-hours they were responsible for during it
+    ///
+    /// The cost of an hour that no one was responsible for (such as the
+    /// refrigerator running while everyone is out) is split evenly.
+    ///
+    /// # Examples
+    /// ```
+    /// use chrono::naive::NaiveDate;
+    /// use roommates::RoommateGroup;
+    /// use roommates::sharing::{HourlyMeterData, HourlyResponsibilityInterval};
+    ///
+    /// let group: RoommateGroup = vec!["Ann", "Bo"].into_iter().collect();
+    /// let meter = HourlyMeterData::from_reader(
+    ///     "start,usage,price\n01/01/2020 17:00,2.0,30\n01/01/2020 18:00,1.0,12\n".as_bytes(),
+    /// ).unwrap();
+    /// let day = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+    /// // Ann is home for the expensive hour
+    /// let record = vec![HourlyResponsibilityInterval::new(
+    ///     group.borrow_by_name("Ann").unwrap(),
+    ///     day.and_hms_opt(16, 0, 0).unwrap(),
+    ///     day.and_hms_opt(18, 0, 0).unwrap(),
+    ///     0,
+    /// ).unwrap()].into_iter().collect();
+    /// let shares = group.hourly_shares(&record, &meter);
+    /// assert_eq!(shares.totals()[group.borrow_by_name("Ann").unwrap()], 66.0);
+    /// ```
+    pub fn hourly_shares(
+        &self,
+        responsibility_intervals: &HourlyResponsibilityRecord,
+        meter: &HourlyMeterData,
+    ) -> HourlyShares<'_> {
+        let mut roommates = self.iter().collect::<Vec<_>>();
+        roommates.sort();
+        let hours = meter
+            .hours
+            .iter()
+            .map(|(start, (usage, price))| {
+                let cost = usage * price;
+                let occupancy = roommates
+                    .iter()
+                    .map(|roommate| {
+                        responsibility_intervals.roommate_occupancy_in_hour(roommate, *start)
+                    })
+                    .collect::<Vec<_>>();
+                let total = occupancy.iter().sum::<f64>();
+                let shares = roommates
+                    .iter()
+                    .zip(occupancy)
+                    .map(|(roommate, occupancy)| {
+                        let share = if total > 0.0 {
+                            cost * occupancy / total
+                        } else {
+                            cost / f64::from(self.count())
+                        };
+                        (*roommate, share)
+                    })
+                    .collect();
+                HourShare {
+                    start: *start,
+                    cost,
+                    shares,
+                }
+            })
+            .collect();
+        HourlyShares { group: self, hours }
+    }
+}
+
+impl<'a> HourlyShares<'a> {
+    pub fn hours(&self) -> &[HourShare<'a>] {
+        &self.hours
+    }
+
+    /// How much each roommate owes (in minor units) over every hour
+    pub fn totals(&self) -> HashMap<&'a Roommate, f64> {
+        let mut totals = HashMap::new();
+        for (roommate, share) in self.hours.iter().flat_map(|hour| &hour.shares) {
+            *totals.entry(*roommate).or_insert(0.0) += share;
+        }
+        totals
+    }
+
+    /// The exact proportion of the total cost that each roommate owes, to
+    /// split a bill for the hours with
+    ///
+    /// A roommate whose hours net to a credit gets no share, and if the hours
+    /// as a whole net to a credit the bill is split evenly.
+    pub fn responsibilities(&self) -> Result<ResponsibilitySplit<'a>, Error> {
+        let totals = self.totals();
+        let net = totals.values().sum::<f64>();
+        // credits, and totals that aren't finite, count as nothing
+        let owed = totals
+            .into_iter()
+            .map(|(roommate, total)| {
+                let owed = BigRational::from_float(total.max(0.0));
+                (roommate, owed.unwrap_or_else(BigRational::zero))
+            })
+            .collect::<Vec<_>>();
+        let total = owed.iter().map(|(_, owed)| owed).sum::<BigRational>();
+        let map = if net > 0.0 && !total.is_zero() {
+            owed.into_iter()
+                .map(|(roommate, owed)| (roommate, owed / &total))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        self.group.build_exact_split(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::DateInterval;
+    use crate::test_fixtures::roommate;
+    use chrono::naive::NaiveDate;
+    use num::BigRational;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn split_by_hour() {
+        let group: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let meter = HourlyMeterData::from_reader(
+            "start,usage,price
+01/01/2020 00:00,1.0,10
+01/01/2020 01:00,2.0,30
+01/01/2020 02:00,1.0,10
+01/01/2020 03:00,3.0,10
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let record = vec![
+            HourlyResponsibilityInterval::new(roommate(&group, "a"), at(0, 0), at(2, 0), 0)
+                .unwrap(),
+            HourlyResponsibilityInterval::new(roommate(&group, "b"), at(0, 0), at(1, 0), 0)
+                .unwrap(),
+            // nobody home from 2:00 to 3:00
+            HourlyResponsibilityInterval::new(roommate(&group, "a"), at(3, 0), at(4, 0), 0)
+                .unwrap(),
+            HourlyResponsibilityInterval::new(roommate(&group, "b"), at(3, 0), at(3, 30), 0)
+                .unwrap(),
+        ]
+        .into_iter()
+        .collect();
+        let shares = group.hourly_shares(&record, &meter);
+        assert_eq!(
+            shares.hours()[1].shares,
+            vec![(roommate(&group, "a"), 60.0), (roommate(&group, "b"), 0.0)]
+        );
+        let totals = shares.totals();
+        assert_eq!(totals[roommate(&group, "a")], 5.0 + 60.0 + 5.0 + 20.0);
+        assert_eq!(totals[roommate(&group, "b")], 5.0 + 5.0 + 10.0);
+        assert_eq!(
+            shares.responsibilities().unwrap().hash_map()[roommate(&group, "b")],
+            BigRational::new(2.into(), 11.into())
+        );
+    }
+
+    #[test]
+    fn whole_days() {
+        let group: RoommateGroup = vec!["a"].into_iter().collect();
+        let days = DateInterval::new((2020, 1, 1), (2020, 1, 2)).unwrap();
+        let record: ResponsibilityRecord =
+            vec![ResponsibilityInterval::new(roommate(&group, "a"), days, 1)]
+                .into_iter()
+                .collect();
+        let hourly = HourlyResponsibilityRecord::from(&record);
+        let a = roommate(&group, "a");
+        assert_eq!(hourly.roommate_occupancy_in_hour(a, at(23, 0)), 2.0);
+        assert_eq!(
+            hourly.roommate_occupancy_in_hour(a, at(23, 0) + Duration::days(2)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn negative_usage_and_prices() {
+        let meter = HourlyMeterData::from_reader(
+            "start,usage,price
+01/01/2020 12:00,-2.0,10
+01/01/2020 13:00,1.0,-5
+01/01/2020 14:00,3.0,10
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(meter.cost(), -20.0 - 5.0 + 30.0);
+    }
+
+    #[test]
+    fn credits_get_no_share() {
+        let group: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let responsibilities = |meter: &str| {
+            let meter = HourlyMeterData::from_reader(meter.as_bytes()).unwrap();
+            let record = vec![
+                HourlyResponsibilityInterval::new(roommate(&group, "a"), at(0, 0), at(1, 0), 0)
+                    .unwrap(),
+                HourlyResponsibilityInterval::new(roommate(&group, "b"), at(1, 0), at(2, 0), 0)
+                    .unwrap(),
+            ]
+            .into_iter()
+            .collect();
+            group
+                .hourly_shares(&record, &meter)
+                .responsibilities()
+                .unwrap()
+                .hash_map()[roommate(&group, "a")]
+            .clone()
+        };
+        // b's hour is a credit, so a owes the whole bill
+        assert_eq!(
+            responsibilities(
+                "start,usage,price\n01/01/2020 00:00,1.0,30\n01/01/2020 01:00,-1.0,10\n"
+            ),
+            BigRational::from_integer(1.into())
+        );
+        // the hours net to a credit, so the bill is split evenly
+        assert_eq!(
+            responsibilities(
+                "start,usage,price\n01/01/2020 00:00,1.0,10\n01/01/2020 01:00,-1.0,30\n"
+            ),
+            BigRational::new(1.into(), 2.into())
+        );
+    }
+
+    #[test]
+    fn malformed_rows() {
+        for csv in &[
+            "start,usage,price\n01/01/2020 00:00,1.0\n",
+            "start,usage,price\n2020-01-01 00:00,1.0,10\n",
+            "start,usage,price\n01/01/2020 00:00,NaN,10\n",
+            "start,usage,price\n01/01/2020 00:00,1.0,10\n01/01/2020 00:00,1.0,10\n",
+        ] {
+            assert!(matches!(
+                HourlyMeterData::from_reader(csv.as_bytes()),
+                Err(Error::InvalidMeterData(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use num::{BigRational, One, Signed, Zero};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use steel_cent::{currency::Currency, Money};
+
+use super::bill::Bill;
+use super::estimation::{covariate_columns, Covariates};
+use super::interval::{DateInterval, ResponsibilityRecord};
+use super::regression::{regression_error, LeastSquares};
+use super::roommate::{Roommate, RoommateGroup};
+use super::split::ResponsibilitySplit;
+use crate::{Error, EstimationError};
+
+/// The probability that a confidence interval should cover
+const CONFIDENCE_LEVEL: f64 = 0.95;
+
+/// How much one of a roommate's person-days costs
+///
+/// Amounts are in minor units (e.g. cents) of the bills' currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageIntensity {
+    pub cost_per_person_day: f64,
+    /// The range that the cost falls in with 95% confidence
+    pub confidence_interval: (f64, f64),
+    /// The cost over the mean cost of all roommates with an intensity
+    pub relative: f64,
+}
+
+/// The usage intensity of each roommate, fit from the bill history
+///
+/// [`RoommateGroup::weighted_responsibilities`] uses them to weight each
+/// roommate's share of the non-shared part of a bill. Check the confidence
+/// intervals before agreeing to use them.
+///
+/// [`RoommateGroup::weighted_responsibilities`]: ../struct.RoommateGroup.html#method.weighted_responsibilities
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageIntensities {
+    currency: Currency,
+    intensities: BTreeMap<Roommate, UsageIntensity>,
+}
+
+impl UsageIntensities {
+    /// The usage intensity of `roommate`, unless they weren't responsible for
+    /// anyone during the history
+    pub fn get(&self, roommate: &Roommate) -> Option<&UsageIntensity> {
+        self.intensities.get(roommate)
+    }
+
+    /// Returns an `Iterator` over each roommate with a usage intensity, in
+    /// order of their names
+    pub fn iter(&self) -> impl Iterator<Item = (&Roommate, &UsageIntensity)> {
+        self.intensities.iter()
+    }
+}
+
+impl RoommateGroup {
+    /// Fits a separate cost per person-day for each roommate from the bill
+    /// history
+    ///
+    /// Regresses each bill's amount due minus its fixed cost on each
+    /// roommate's occupancy over the bill (counting their guests) and on the
+    /// bills' covariates. Roommates who weren't responsible for anyone during
+    /// the history are left out.
+    ///
+    /// Returns an [`EmptyBillList`] error if there are no bills, a
+    /// [`MixedCurrencies`] error if they are not all in the same currency, a
+    /// [`Regression`] error if the fit fails, and a [`NonPositiveIntensity`]
+    /// error if someone's usage seems to cost nothing.
+    ///
+    /// [`EmptyBillList`]: enum.Error.html#variant.EmptyBillList
+    /// [`MixedCurrencies`]: enum.Error.html#variant.MixedCurrencies
+    /// [`Regression`]: enum.EstimationError.html#variant.Regression
+    /// [`NonPositiveIntensity`]: enum.EstimationError.html#variant.NonPositiveIntensity
+    pub fn usage_intensities<'a, I>(
+        &self,
+        responsibility_intervals: &ResponsibilityRecord,
+        bill_history: I,
+    ) -> Result<UsageIntensities, Error>
+    where
+        I: IntoIterator<Item = (&'a Bill, &'a Covariates)>,
+    {
+        let history = bill_history.into_iter().collect::<Vec<_>>();
+        let currency = history
+            .first()
+            .ok_or(Error::EmptyBillList)?
+            .0
+            .amount_due()
+            .currency;
+        if history
+            .iter()
+            .any(|(bill, _)| bill.amount_due().currency != currency)
+        {
+            return Err(Error::MixedCurrencies);
+        }
+        let y = history
+            .iter()
+            .map(|(bill, _)| {
+                (bill.amount_due().minor_amount() - bill.fixed_cost().minor_amount()) as f64
+            })
+            .collect::<Vec<_>>();
+        let mut roommates = self.iter().collect::<Vec<_>>();
+        roommates.sort();
+        let mut occupancy = vec![];
+        for roommate in roommates {
+            let column = history
+                .iter()
+                .map(|(bill, _)| {
+                    let occupancy = responsibility_intervals
+                        .roommate_occupancy_over(roommate, bill.usage_period())?;
+                    Ok(occupancy as f64)
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            if column.iter().any(|occupancy| *occupancy != 0.0) {
+                occupancy.push((roommate, column));
+            }
+        }
+        if occupancy.is_empty() {
+            return Err(regression_error(String::from(
+                "nobody was responsible for anyone during the history",
+            )));
+        }
+        let covariates = covariate_columns(
+            &Covariates::new(),
+            history.iter().map(|(_, covariates)| *covariates),
+        )?;
+        let columns = occupancy
+            .iter()
+            .map(|(_, column)| column.clone())
+            .chain(covariates.into_iter().map(|(_, _, column)| column))
+            .collect::<Vec<_>>();
+        let fit = LeastSquares::fit(&y, &columns, None)?;
+        let costs = &fit.coefficients()[1..=occupancy.len()];
+        if let Some((i, cost)) = costs.iter().enumerate().find(|(_, cost)| **cost <= 0.0) {
+            return Err(Error::Estimation(EstimationError::NonPositiveIntensity {
+                roommate: occupancy[i].0.to_string(),
+                cost_per_person_day: *cost,
+            }));
+        }
+        let mean = costs.iter().sum::<f64>() / costs.len() as f64;
+        let intensities = occupancy
+            .iter()
+            .zip(costs)
+            .enumerate()
+            .map(|(i, ((roommate, _), cost))| {
+                let intensity = UsageIntensity {
+                    cost_per_person_day: *cost,
+                    confidence_interval: fit.confidence_interval(i + 1, CONFIDENCE_LEVEL),
+                    relative: cost / mean,
+                };
+                ((*roommate).clone(), intensity)
+            })
+            .collect();
+        Ok(UsageIntensities {
+            currency,
+            intensities,
+        })
+    }
+
+    /// Like [`individual_responsibilities`], but with each roommate's
+    /// occupancy weighted by their relative usage intensity
+    ///
+    /// Roommates without a usage intensity, or with a relative intensity
+    /// that isn't a positive number, count as average. The weights are used
+    /// exactly as they are, without rounding.
+    ///
+    /// [`individual_responsibilities`]: struct.RoommateGroup.html#method.individual_responsibilities
+    pub fn weighted_responsibilities(
+        &self,
+        responsibility_intervals: &ResponsibilityRecord,
+        billing_period: DateInterval,
+        intensities: &UsageIntensities,
+    ) -> Result<ResponsibilitySplit<'_>, Error> {
+        let weighted = self
+            .iter()
+            .map(|roommate| {
+                let occupancy =
+                    responsibility_intervals.roommate_occupancy_over(roommate, billing_period)?;
+                let weight = intensities
+                    .get(roommate)
+                    .and_then(|i| BigRational::from_float(i.relative))
+                    .filter(|relative| relative.is_positive())
+                    .unwrap_or_else(BigRational::one);
+                Ok((
+                    roommate,
+                    weight * BigRational::from_integer(occupancy.into()),
+                ))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let total = weighted
+            .iter()
+            .map(|(_, weighted)| weighted)
+            .sum::<BigRational>();
+        let map = weighted
+            .into_iter()
+            .map(|(roommate, weighted)| {
+                let share = if total.is_zero() {
+                    BigRational::zero()
+                } else {
+                    weighted / &total
+                };
+                (roommate, share)
+            })
+            .collect::<HashMap<_, _>>();
+        self.build_exact_split(map)
+    }
+}
+
+impl fmt::Display for UsageIntensities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let money = |minor: f64| Money::of_minor(self.currency, minor.round() as i64);
+        write!(f, "cost per person-day")?;
+        for (roommate, intensity) in &self.intensities {
+            let (low, high) = intensity.confidence_interval;
+            write!(
+                f,
+                "\n\t{}: {} (95% confidence interval {} to {}), {:.2} times the average",
+                roommate,
+                money(intensity.cost_per_person_day),
+                money(low),
+                money(high),
+                intensity.relative
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::ResponsibilityInterval;
+    use crate::test_fixtures::roommate;
+    use num::ToPrimitive;
+    use steel_cent::currency::USD;
+
+    #[test]
+    fn home_office_uses_more() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        // days that each roommate is home in each month
+        let days = [
+            [28, 28, 10],
+            [14, 28, 28],
+            [28, 7, 28],
+            [20, 28, 14],
+            [28, 14, 21],
+            [7, 21, 28],
+            [28, 28, 28],
+        ];
+        let mut intervals = vec![];
+        let mut bills = vec![];
+        for (month, days) in (1..).zip(&days) {
+            let mut cost = 10_00;
+            for (name, days) in ["a", "b", "c"].iter().zip(days) {
+                let interval = DateInterval::new((2020, month, 1), (2020, month, *days)).unwrap();
+                intervals.push(ResponsibilityInterval::new(
+                    roommate(&group, name),
+                    interval,
+                    0,
+                ));
+                // b works from home
+                cost += if *name == "b" { 40 } else { 20 } * *days as i64;
+            }
+            // a little noise
+            cost += (month as i64 % 3) * 10;
+            let period = DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap();
+            bills.push((
+                Bill::new(Money::of_minor(USD, cost), period),
+                Covariates::new(),
+            ));
+        }
+        let record: ResponsibilityRecord = intervals.into_iter().collect();
+        let intensities = group
+            .usage_intensities(&record, bills.iter().map(|(bill, c)| (bill, c)))
+            .unwrap();
+        let b = intensities.get(roommate(&group, "b")).unwrap();
+        assert!((b.cost_per_person_day - 40.0).abs() < 1.0);
+        assert!(b.confidence_interval.0 < 40.0 && 40.0 < b.confidence_interval.1);
+        assert!((b.relative - 1.5).abs() < 0.05);
+        let split = group
+            .weighted_responsibilities(
+                &record,
+                DateInterval::new((2020, 7, 1), (2020, 7, 28)).unwrap(),
+                &intensities,
+            )
+            .unwrap();
+        let share = split.hash_map()[roommate(&group, "b")].to_f64().unwrap();
+        assert!((share - 0.5).abs() < 0.02);
+        assert_eq!(intensities.to_string().lines().count(), 4);
+    }
+
+    #[test]
+    fn weighted_occupancy_beyond_a_u32() {
+        let group: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let period = DateInterval::new((2020, 1, 1), (2020, 1, 28)).unwrap();
+        // 28 days of 10 million people, which fits in a u32 unweighted
+        let record: ResponsibilityRecord = vec![
+            ResponsibilityInterval::new(roommate(&group, "a"), period, 9_999_999),
+            ResponsibilityInterval::new(roommate(&group, "b"), period, 0),
+        ]
+        .into_iter()
+        .collect();
+        let intensity = |relative| UsageIntensity {
+            cost_per_person_day: relative,
+            confidence_interval: (relative, relative),
+            relative,
+        };
+        let intensities = UsageIntensities {
+            currency: USD,
+            intensities: vec![
+                (roommate(&group, "a").clone(), intensity(0.5)),
+                (roommate(&group, "b").clone(), intensity(1.5)),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let split = group
+            .weighted_responsibilities(&record, period, &intensities)
+            .unwrap()
+            .hash_map();
+        assert_eq!(
+            split[roommate(&group, "b")],
+            BigRational::new(3.into(), 10_000_003.into())
+        );
+    }
+
+    #[test]
+    fn relative_intensities_are_exact() {
+        let group: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let period = DateInterval::new((2020, 1, 1), (2020, 1, 28)).unwrap();
+        let record: ResponsibilityRecord = vec![
+            ResponsibilityInterval::new(roommate(&group, "a"), period, 0),
+            ResponsibilityInterval::new(roommate(&group, "b"), period, 0),
+        ]
+        .into_iter()
+        .collect();
+        let intensity = |relative| UsageIntensity {
+            cost_per_person_day: relative,
+            confidence_interval: (relative, relative),
+            relative,
+        };
+        // a's weight would round to a thousandth
+        let intensities = UsageIntensities {
+            currency: USD,
+            intensities: vec![
+                (roommate(&group, "a").clone(), intensity(1.0 / 1024.0)),
+                (roommate(&group, "b").clone(), intensity(2.0 - 1.0 / 1024.0)),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let split = group
+            .weighted_responsibilities(&record, period, &intensities)
+            .unwrap()
+            .hash_map();
+        assert_eq!(
+            split[roommate(&group, "a")],
+            BigRational::new(1.into(), 2048.into())
+        );
+    }
+
+    #[test]
+    fn no_history() {
+        let group: RoommateGroup = vec!["a"].into_iter().collect();
+        let record: ResponsibilityRecord = vec![].into_iter().collect();
+        assert_eq!(
+            group.usage_intensities(&record, vec![]),
+            Err(Error::EmptyBillList)
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use chrono::{naive::NaiveDate, Duration};
 use std::cmp::{max, min};
+use std::convert::TryFrom;
 use std::iter::FromIterator;
 use std::slice::Iter;
 
@@ -63,10 +64,20 @@ impl<'a> ResponsibilityInterval<'a> {
     /// );
     /// assert_eq!(joes_friends_visiting.roommate(), &joe);
     /// ```
-    pub fn roommate(&self) -> &Roommate {
+    pub fn roommate(&self) -> &'a Roommate {
         self.roommate
     }
 
+    /// Returns the days of the interval
+    pub(crate) fn interval(&self) -> DateInterval {
+        self.interval
+    }
+
+    /// Returns the number of people beyond the responsible roommate
+    pub(crate) fn additional_people(&self) -> u32 {
+        self.additional_people
+    }
+
     /// Returns the total number of people represented by the `ResponsibilityInterval`
     ///
     /// # Examples
@@ -99,7 +110,7 @@ pub struct ResponsibilityRecord<'a> {
     intervals: Vec<ResponsibilityInterval<'a>>,
 }
 
-impl ResponsibilityRecord<'_> {
+impl<'a> ResponsibilityRecord<'a> {
     /// Returns an `Iterator` that visits each [`ResponsibilityInterval`] in the
     /// `ResponsibilityRecord`
     ///
@@ -126,7 +137,7 @@ impl ResponsibilityRecord<'_> {
     /// assert_eq!(record_iter.next().unwrap().roommate(), &bob);
     /// assert_eq!(record_iter.next().unwrap().roommate(), &joe);
     /// ```
-    pub fn iter(&self) -> Iter<ResponsibilityInterval> {
+    pub fn iter(&self) -> Iter<'_, ResponsibilityInterval<'a>> {
         self.intervals.iter()
     }
 
@@ -170,10 +181,42 @@ impl ResponsibilityRecord<'_> {
     ///     0,
     /// );
     /// ```
+    ///
+    /// # Panics
+    /// Panics if the occupancy does not fit in a `u32`; see
+    /// [`checked_occupancy_over`] for an error instead.
+    ///
+    /// [`checked_occupancy_over`]: struct.ResponsibilityRecord.html#method.checked_occupancy_over
     pub fn occupancy_over(&self, period: DateInterval) -> u32 {
+        self.checked_occupancy_over(period)
+            .expect("occupancy does not fit in a u32")
+    }
+
+    /// Like [`occupancy_over`], but returns an [`Overflow`] error if the
+    /// occupancy does not fit in a `u32`
+    ///
+    /// [`occupancy_over`]: struct.ResponsibilityRecord.html#method.occupancy_over
+    /// [`Overflow`]: enum.Error.html#variant.Overflow
+    pub fn checked_occupancy_over(&self, period: DateInterval) -> Result<u32, Error> {
+        let occupancy = self.iter().try_fold(0u64, |total, r| {
+            let people = r.additional_people as u64 + 1;
+            let days = r.interval.num_days_bounded_by(period) as u64;
+            total.checked_add(people * days).ok_or(Error::Overflow)
+        })?;
+        u32::try_from(occupancy).map_err(|_| Error::Overflow)
+    }
+
+    /// Occupancy over a given interval that `roommate` is responsible for
+    pub(crate) fn roommate_occupancy_over(
+        &self,
+        roommate: &Roommate,
+        period: DateInterval,
+    ) -> Result<u32, Error> {
         self.iter()
-            .map(|r| r.num_people() * r.interval.num_days_bounded_by(period))
-            .sum()
+            .filter(|i| i.roommate() == roommate)
+            .cloned()
+            .collect::<ResponsibilityRecord>()
+            .checked_occupancy_over(period)
     }
 }
 
@@ -217,9 +260,11 @@ impl DateInterval {
         (start_year, start_month, start_day): (i32, u32, u32),
         (end_year, end_month, end_day): (i32, u32, u32),
     ) -> Result<Self, Error> {
-        let start = NaiveDate::from_ymd(start_year, start_month, start_day);
-        let end = NaiveDate::from_ymd(end_year, end_month, end_day);
-        Ok(DateInterval::create_interval(start, end)?)
+        let start = NaiveDate::from_ymd_opt(start_year, start_month, start_day)
+            .expect("invalid or out-of-range date");
+        let end = NaiveDate::from_ymd_opt(end_year, end_month, end_day)
+            .expect("invalid or out-of-range date");
+        DateInterval::create_interval(start, end)
     }
 
     /// Creates a new `DateInterval` from `"month/day/year"` strings
@@ -244,7 +289,7 @@ impl DateInterval {
             .map_err(|source| Error::InvalidDate { source })?;
         let end = NaiveDate::parse_from_str(end, "%m/%d/%Y")
             .map_err(|source| Error::InvalidDate { source })?;
-        Ok(DateInterval::create_interval(start, end)?)
+        DateInterval::create_interval(start, end)
     }
 
     fn create_interval(start: NaiveDate, end: NaiveDate) -> Result<Self, Error> {
@@ -284,6 +329,24 @@ impl DateInterval {
         self.1
     }
 
+    /// The number of days in the interval
+    ///
+    /// # Examples
+    /// ```
+    /// use roommates::DateInterval;
+    ///
+    /// let january = DateInterval::new((2020, 1, 1), (2020, 1, 31)).unwrap();
+    /// assert_eq!(january.num_days(), 31);
+    /// ```
+    pub fn num_days(self) -> u32 {
+        self.num_days_bounded_by(self)
+    }
+
+    /// Returns an `Iterator` over each day in the interval, in order
+    pub(crate) fn days(self) -> impl Iterator<Item = NaiveDate> {
+        (0..self.num_days()).map(move |day| self.start() + Duration::days(day as i64))
+    }
+
     /// The number of days in the interval that lie in a second interval
     ///
     /// This has the effect of truncating the interval and then counting
@@ -371,6 +434,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn too_many_people() {
+        let me = Roommate::new("me");
+        let january = DateInterval::new((2020, 1, 1), (2020, 1, 31)).unwrap();
+        let intervals = vec![ResponsibilityInterval::new(&me, january, u32::MAX / 2)]
+            .into_iter()
+            .collect::<ResponsibilityRecord>();
+        assert_eq!(
+            intervals.checked_occupancy_over(january),
+            Err(Error::Overflow)
+        );
+    }
+
     #[test]
     fn intervals_bounding() {
         let april = DateInterval::new((2020, 4, 1), (2020, 4, 30)).unwrap();
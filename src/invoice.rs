@@ -1,13 +1,61 @@
 use itertools::Itertools;
-use num::rational::Ratio;
+use num::{BigRational, ToPrimitive};
 use std::collections::HashMap;
-use std::error::Error;
 use std::fmt;
-use steel_cent::Money;
+use steel_cent::{currency::Currency, Money};
 
 use super::bill::{Bill, SharedBill};
+use super::estimation::{Covariates, EstimationModel};
+use super::exchange::{ExchangeRateProvider, SameCurrency};
 use super::interval::ResponsibilityRecord;
 use super::roommate::{Roommate, RoommateGroup};
+use super::rounding::RoundingPolicy;
+use super::shared_cost::{EstimationConfig, EstimationReport};
+use super::Error;
+
+/// The invoices for a list of bills
+///
+/// Bills whose shared cost could not be found, or that could not be
+/// converted into the invoices' currency, are left out of the invoices and
+/// listed separately, along with the reason.
+pub struct Invoices {
+    invoices: Vec<Invoice>,
+    failed_bills: Vec<(String, Error)>,
+    estimation_reports: Vec<(String, EstimationReport)>,
+    house_fund: Vec<(String, Money)>,
+}
+
+impl Invoices {
+    /// Returns one [`Invoice`] for each roommate
+    ///
+    /// [`Invoice`]: struct.Invoice.html
+    pub fn invoices(&self) -> &[Invoice] {
+        &self.invoices
+    }
+
+    /// Returns the label of each bill that was left out of the invoices,
+    /// along with the reason
+    pub fn failed_bills(&self) -> &[(String, Error)] {
+        &self.failed_bills
+    }
+
+    /// Returns the label of each bill whose shared cost was estimated, along
+    /// with how it was estimated
+    pub fn estimation_reports(&self) -> &[(String, EstimationReport)] {
+        &self.estimation_reports
+    }
+
+    /// Returns the label of each bill whose charges don't add up to its
+    /// amount due, along with how much more was charged than is due
+    ///
+    /// With a rounding policy like [`WholeUnits`] the difference goes into
+    /// the house fund, or comes out of it if negative.
+    ///
+    /// [`WholeUnits`]: struct.WholeUnits.html
+    pub fn house_fund(&self) -> &[(String, Money)] {
+        &self.house_fund
+    }
+}
 
 pub struct Invoice {
     to: Roommate,
@@ -15,107 +63,316 @@ pub struct Invoice {
     components: Vec<InvoiceComponent>,
 }
 
-struct InvoiceComponent {
+impl Invoice {
+    /// Returns the charge for each bill
+    pub fn components(&self) -> &[InvoiceComponent] {
+        &self.components
+    }
+
+    /// Returns the label of each bill whose charge the rounding policy
+    /// moved away from the exact share, along with the adjustment
+    pub fn rounding_adjustments(&self) -> impl Iterator<Item = (&str, &RoundingAdjustment)> {
+        self.components.iter().filter_map(|c| {
+            c.rounding_adjustment
+                .as_ref()
+                .map(|adjustment| (c.label.as_str(), adjustment))
+        })
+    }
+}
+
+/// A roommate's charge for one bill
+pub struct InvoiceComponent {
     label: String,
     amount_due: Money,
     shared_amount: Money,
-    responsibility_proportion: Ratio<u32>,
+    responsibility_proportion: BigRational,
+    charge: Money,
+    rounding_adjustment: Option<RoundingAdjustment>,
+    conversion: Option<Conversion>,
 }
 
-pub enum SharingData<I: IntoIterator<Item = (Bill, Option<f64>)>> {
+impl InvoiceComponent {
+    /// The label of the bill
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// How much the roommate is charged for the bill
+    pub fn charge(&self) -> Money {
+        self.charge
+    }
+
+    /// The rate the bill was converted into the invoice's currency at, if it
+    /// was in another currency
+    pub fn exchange_rate(&self) -> Option<f64> {
+        self.conversion.map(|conversion| conversion.rate)
+    }
+}
+
+/// The original amount due of a bill that was converted into the invoice's
+/// currency, and the rate used
+#[derive(Clone, Copy)]
+struct Conversion {
+    original_amount_due: Money,
+    rate: f64,
+}
+
+/// How much a rounding policy moved a charge away from the exact share, to
+/// the nearest minor unit
+pub struct RoundingAdjustment {
+    policy: String,
+    amount: Money,
+}
+
+impl RoundingAdjustment {
+    /// The name of the rounding policy
+    pub fn policy(&self) -> &str {
+        &self.policy
+    }
+
+    /// How much the charge moved, which is negative if it went down
+    pub fn amount(&self) -> Money {
+        self.amount
+    }
+}
+
+/// A bill to invoice, along with what is needed to find its shared amount
+pub enum SharingData<I: IntoIterator<Item = (Bill, Covariates)>> {
+    /// A bill whose shared amount is its fixed cost
     Fixed(Bill),
-    Variable((Bill, Option<f64>), I),
+    /// A bill and its covariates, the history of earlier bills (with their
+    /// covariates) used to estimate its shared amount, the model to estimate
+    /// it with, and when to accept the estimate
+    Variable(
+        (Bill, Covariates),
+        I,
+        Box<dyn EstimationModel>,
+        EstimationConfig,
+    ),
 }
 
 impl RoommateGroup {
+    /// Splits each bill and collects the charges into one [`Invoice`] per
+    /// roommate
+    ///
+    /// If the shared cost of a bill cannot be found, the bill is listed in
+    /// [`Invoices::failed_bills`] and the rest are still invoiced.
+    ///
+    /// Returns an [`EmptyBillList`] error if there are no bills, and a
+    /// [`MixedCurrencies`] error if the bills are not all in the same
+    /// currency; use [`generate_invoices_in`] to convert them.
+    ///
+    /// [`Invoice`]: splitting/struct.Invoice.html
+    /// [`Invoices::failed_bills`]: splitting/struct.Invoices.html#method.failed_bills
+    /// [`EmptyBillList`]: enum.Error.html#variant.EmptyBillList
+    /// [`MixedCurrencies`]: enum.Error.html#variant.MixedCurrencies
+    /// [`generate_invoices_in`]: struct.RoommateGroup.html#method.generate_invoices_in
     pub fn generate_invoices<'a, I, J>(
         &self,
         bills: J,
         responsibility_intervals: &ResponsibilityRecord,
-    ) -> Vec<Invoice>
+        rounding: &mut dyn RoundingPolicy,
+    ) -> Result<Invoices, Error>
     where
         J: IntoIterator<Item = (&'a str, SharingData<I>)>,
-        I: IntoIterator<Item = (Bill, Option<f64>)>,
+        I: IntoIterator<Item = (Bill, Covariates)>,
+    {
+        let bills = bills.into_iter().collect::<Vec<_>>();
+        let currency_of = |(_, sharing_data): &(&str, SharingData<I>)| match sharing_data {
+            SharingData::Fixed(bill) | SharingData::Variable((bill, _), ..) => {
+                bill.amount_due().currency
+            }
+        };
+        let currency = currency_of(bills.first().ok_or(Error::EmptyBillList)?);
+        if bills.iter().any(|bill| currency_of(bill) != currency) {
+            return Err(Error::MixedCurrencies);
+        }
+        self.generate_invoices_in(
+            currency,
+            &SameCurrency,
+            bills,
+            responsibility_intervals,
+            rounding,
+        )
+    }
+
+    /// Like [`generate_invoices`], but first converts every bill into
+    /// `currency` using `exchange_rates`
+    ///
+    /// The rate used for each bill is recorded on the invoices, as
+    /// [`InvoiceComponent::exchange_rate`]. A bill that cannot be converted,
+    /// such as one with no exchange rate, is listed in
+    /// [`Invoices::failed_bills`] and the rest are still invoiced.
+    ///
+    /// [`generate_invoices`]: struct.RoommateGroup.html#method.generate_invoices
+    /// [`InvoiceComponent::exchange_rate`]: splitting/struct.InvoiceComponent.html#method.exchange_rate
+    /// [`Invoices::failed_bills`]: splitting/struct.Invoices.html#method.failed_bills
+    pub fn generate_invoices_in<'a, I, J>(
+        &self,
+        currency: Currency,
+        exchange_rates: &dyn ExchangeRateProvider,
+        bills: J,
+        responsibility_intervals: &ResponsibilityRecord,
+        rounding: &mut dyn RoundingPolicy,
+    ) -> Result<Invoices, Error>
+    where
+        J: IntoIterator<Item = (&'a str, SharingData<I>)>,
+        I: IntoIterator<Item = (Bill, Covariates)>,
     {
         let mut invoice_components: HashMap<Roommate, Vec<InvoiceComponent>> = HashMap::new();
-        let bill_list = bills
-            .into_iter()
-            .map(|(label, sharing_data)| match sharing_data {
-                SharingData::Variable(current_bill, history) => {
-                    estimate_shared_bills(label, current_bill, history, responsibility_intervals)
-                        .expect("estimating failed")
+        let mut failed_bills = Vec::new();
+        let mut estimation_reports = Vec::new();
+        let mut house_fund = Vec::new();
+        let mut bills = bills.into_iter().peekable();
+        if bills.peek().is_none() {
+            return Err(Error::EmptyBillList);
+        }
+        for (label, sharing_data) in bills {
+            let shared_bill = match sharing_data {
+                SharingData::Variable(current_bill, history, model, config) => {
+                    match estimate_shared_bill(
+                        current_bill,
+                        history,
+                        model.as_ref(),
+                        &config,
+                        responsibility_intervals,
+                    ) {
+                        Ok((shared_bill, report)) => {
+                            estimation_reports.push((String::from(label), report));
+                            shared_bill
+                        }
+                        Err(error) => {
+                            failed_bills.push((String::from(label), error));
+                            continue;
+                        }
+                    }
                 }
-                SharingData::Fixed(bill) => (
-                    label,
-                    SharedBill::from_fixed(
-                        Bill::new_with_fixed_cost(
-                            bill.amount_due(),
-                            bill.usage_period(),
-                            bill.amount_due(),
-                        )
-                        .expect("invalid fixed cost"),
-                    ),
-                ),
-            })
-            .map(|(label, shared_bill)| {
-                let split = self.individual_responsibilities(
-                    responsibility_intervals,
-                    shared_bill.usage_period(),
-                );
-                for (roommate, share) in split.hash_map().into_iter() {
-                    invoice_components
-                        .entry(roommate.clone())
-                        .or_insert_with(|| vec![])
-                        .push(InvoiceComponent {
-                            label: String::from(label),
-                            responsibility_proportion: share,
-                            amount_due: shared_bill.amount_due(),
-                            shared_amount: shared_bill.shared_amount(),
-                        })
+                SharingData::Fixed(bill) => match Bill::new_with_fixed_cost(
+                    bill.amount_due(),
+                    bill.usage_period(),
+                    bill.amount_due(),
+                ) {
+                    Ok(bill) => SharedBill::from_fixed(bill),
+                    Err(error) => {
+                        failed_bills.push((String::from(label), error));
+                        continue;
+                    }
+                },
+            };
+            let original_amount_due = shared_bill.amount_due();
+            let (shared_bill, rate) = match shared_bill.convert_to(currency, exchange_rates) {
+                Ok(converted) => converted,
+                Err(error) => {
+                    failed_bills.push((String::from(label), error));
+                    continue;
                 }
-                (shared_bill, split)
-            })
-            .collect::<Vec<_>>();
-        self.split_bill_list(bill_list.iter().map(|(b, s)| (b, s)))
+            };
+            let conversion = if original_amount_due.currency == currency {
+                None
+            } else {
+                Some(Conversion {
+                    original_amount_due,
+                    rate,
+                })
+            };
+            let split = self.checked_individual_responsibilities(
+                responsibility_intervals,
+                shared_bill.usage_period(),
+            )?;
+            let charges = self.split(&shared_bill, &split, rounding)?;
+            let charged = charges
+                .values()
+                .try_fold(Money::zero(currency), |total, charge| {
+                    total.checked_add(*charge)
+                })
+                .ok_or(Error::Overflow)?;
+            if charged != shared_bill.amount_due() {
+                house_fund.push((String::from(label), charged - shared_bill.amount_due()));
+            }
+            for (roommate, share) in split.hash_map().into_iter() {
+                let charge = charges[roommate];
+                let exact = self.exact_share(&shared_bill, &share);
+                let charged = BigRational::from_integer(charge.minor_amount().into());
+                let adjustment = (charged - exact)
+                    .round()
+                    .to_integer()
+                    .to_i64()
+                    .map(|minor| Money::of_minor(currency, minor))
+                    .ok_or(Error::Overflow)?;
+                invoice_components
+                    .entry(roommate.clone())
+                    .or_default()
+                    .push(InvoiceComponent {
+                        label: String::from(label),
+                        responsibility_proportion: share,
+                        amount_due: shared_bill.amount_due(),
+                        shared_amount: shared_bill.shared_amount(),
+                        charge,
+                        rounding_adjustment: if adjustment == Money::zero(currency) {
+                            None
+                        } else {
+                            Some(RoundingAdjustment {
+                                policy: rounding.name(),
+                                amount: adjustment,
+                            })
+                        },
+                        conversion,
+                    })
+            }
+        }
+        let invoices = invoice_components
             .into_iter()
-            .map(|(to, total)| {
-                let components = invoice_components.remove(to).unwrap();
-                let to = to.clone();
-                Invoice {
+            .map(|(to, components)| {
+                let total = components
+                    .iter()
+                    .try_fold(Money::zero(currency), |total, c| {
+                        total.checked_add(c.charge)
+                    })
+                    .ok_or(Error::Overflow)?;
+                Ok(Invoice {
                     to,
                     total,
                     components,
-                }
+                })
             })
-            .collect()
+            .collect::<Result<_, Error>>()?;
+        Ok(Invoices {
+            invoices,
+            failed_bills,
+            estimation_reports,
+            house_fund,
+        })
     }
 }
 
-fn estimate_shared_bills<'a, I>(
-    label: &'a str,
-    (current_bill, current_ti): (Bill, Option<f64>),
-    history_with_ti: I,
+fn estimate_shared_bill<I>(
+    (current_bill, current_covariates): (Bill, Covariates),
+    history_with_covariates: I,
+    model: &dyn EstimationModel,
+    config: &EstimationConfig,
     intervals: &ResponsibilityRecord,
-) -> Result<(&'a str, SharedBill), Box<dyn Error>>
+) -> Result<(SharedBill, EstimationReport), Error>
 where
-    I: IntoIterator<Item = (Bill, Option<f64>)>,
+    I: IntoIterator<Item = (Bill, Covariates)>,
 {
-    let history = history_with_ti
+    let history = history_with_covariates
         .into_iter()
-        .map(|(bill, temperature_index)| {
-            let occupancy = intervals.occupancy_over(bill.usage_period());
-            (bill, occupancy, temperature_index)
+        .map(|(bill, covariates)| {
+            let occupancy = intervals.checked_occupancy_over(bill.usage_period())?;
+            Ok((bill, occupancy, covariates))
         })
-        .collect::<Vec<_>>();
-    let borrowed_history = history.iter().map(|(b, ao, ti)| (b, *ao, *ti));
+        .collect::<Result<Vec<_>, Error>>()?;
+    let borrowed_history = history.iter().map(|(b, ao, c)| (b, *ao, c));
     let current_bill_notes = (
-        intervals.occupancy_over(current_bill.usage_period()),
-        current_ti,
+        intervals.checked_occupancy_over(current_bill.usage_period())?,
+        current_covariates,
     );
-    Ok((
-        label,
-        SharedBill::from_estimate((current_bill, current_bill_notes), borrowed_history)?,
-    ))
+    SharedBill::from_estimate(
+        (current_bill, current_bill_notes),
+        borrowed_history,
+        model,
+        config,
+    )
 }
 
 impl fmt::Display for Invoice {
@@ -139,6 +396,276 @@ impl fmt::Display for InvoiceComponent {
             self.amount_due - self.shared_amount,
             self.amount_due,
             self.label
+        )?;
+        if let Some(conversion) = &self.conversion {
+            write!(f, " ({})", conversion)?;
+        }
+        if let Some(adjustment) = &self.rounding_adjustment {
+            write!(f, " ({})", adjustment)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Conversion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "converted from {} at {}",
+            self.original_amount_due, self.rate
+        )
+    }
+}
+
+impl fmt::Display for RoundingAdjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rounded by {} using {}", self.amount, self.policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::estimation::OrdinaryLeastSquares;
+    use crate::exchange::CsvExchangeRates;
+    use crate::interval::DateInterval;
+    use crate::rounding::{LargestRemainder, WholeUnits};
+    use crate::shared_cost::EstimationFallback;
+    use crate::{EstimationError, InvalidFixedCost};
+    use chrono::NaiveDate;
+    use steel_cent::currency::{EUR, USD};
+
+    type History = Vec<(Bill, Covariates)>;
+
+    fn fixed(amount: Money) -> SharingData<History> {
+        SharingData::Fixed(Bill::new(
+            amount,
+            DateInterval::from_strs("01/01/2020", "01/31/2020").unwrap(),
+        ))
+    }
+
+    #[test]
+    fn bills_in_several_currencies() {
+        let house: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+        let rates =
+            CsvExchangeRates::from_reader("date,from,to,rate\n01/01/2020,EUR,USD,1.5\n".as_bytes())
+                .unwrap();
+        let bills = vec![
+            ("rent", fixed(Money::of_major(USD, 1_000))),
+            ("storage", fixed(Money::of_major(EUR, 20))),
+        ];
+        let invoices = house
+            .generate_invoices_in(USD, &rates, bills, &nobody, &mut LargestRemainder)
+            .unwrap();
+        assert_eq!(invoices.invoices().len(), 2);
+        for invoice in invoices.invoices() {
+            assert_eq!(invoice.total, Money::of_major(USD, 515));
+            let storage = &invoice.components[1];
+            assert_eq!(storage.amount_due, Money::of_major(USD, 30));
+            assert_eq!(storage.exchange_rate(), Some(1.5));
+            assert_eq!(invoice.components()[0].exchange_rate(), None);
+        }
+    }
+
+    #[test]
+    fn missing_exchange_rate_skips_only_that_bill() {
+        let house: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+        let rates = CsvExchangeRates::from_reader(
+            "date,from,to,rate
+02/01/2020,EUR,USD,1.5
+"
+            .as_bytes(),
         )
+        .unwrap();
+        let bills = vec![
+            ("rent", fixed(Money::of_major(USD, 1_000))),
+            ("storage", fixed(Money::of_major(EUR, 20))),
+        ];
+        let invoices = house
+            .generate_invoices_in(USD, &rates, bills, &nobody, &mut LargestRemainder)
+            .unwrap();
+        assert_eq!(invoices.failed_bills().len(), 1);
+        let (label, error) = &invoices.failed_bills()[0];
+        assert_eq!(label, "storage");
+        assert!(matches!(error, Error::MissingExchangeRate { .. }));
+        for invoice in invoices.invoices() {
+            assert_eq!(invoice.total, Money::of_major(USD, 500));
+            assert_eq!(invoice.components().len(), 1);
+        }
+    }
+
+    #[test]
+    fn mixed_currencies_without_exchange_rates() {
+        let house: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+        let bills = vec![
+            ("rent", fixed(Money::of_major(USD, 1_000))),
+            ("storage", fixed(Money::of_major(EUR, 20))),
+        ];
+        assert!(matches!(
+            house.generate_invoices(bills, &nobody, &mut LargestRemainder),
+            Err(Error::MixedCurrencies)
+        ));
+    }
+
+    #[test]
+    fn negative_conversion_skips_only_that_bill() {
+        // a provider that turns euros into a credit, which no bill can be
+        struct NegativeRates;
+        impl ExchangeRateProvider for NegativeRates {
+            fn rate(&self, from: Currency, to: Currency, _date: NaiveDate) -> Result<f64, Error> {
+                Ok(if from == to { 1.0 } else { -1.5 })
+            }
+        }
+        let house: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+        let bills = vec![
+            ("rent", fixed(Money::of_major(USD, 1_000))),
+            ("storage", fixed(Money::of_major(EUR, 20))),
+        ];
+        let invoices = house
+            .generate_invoices_in(USD, &NegativeRates, bills, &nobody, &mut LargestRemainder)
+            .unwrap();
+        assert_eq!(
+            invoices.failed_bills(),
+            &[(
+                String::from("storage"),
+                Error::InvalidFixedCost(InvalidFixedCost::Negative)
+            )]
+        );
+        for invoice in invoices.invoices() {
+            assert_eq!(invoice.total, Money::of_major(USD, 500));
+        }
+    }
+
+    #[test]
+    fn failed_estimate_skips_only_that_bill() {
+        let house: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+        let water = Bill::new(
+            Money::of_major(USD, 30),
+            DateInterval::from_strs("01/01/2020", "01/31/2020").unwrap(),
+        );
+        let bills = vec![
+            ("rent", fixed(Money::of_major(USD, 1_000))),
+            (
+                "water",
+                SharingData::Variable(
+                    (water, Covariates::new()),
+                    vec![],
+                    Box::new(OrdinaryLeastSquares),
+                    EstimationConfig::default(),
+                ),
+            ),
+        ];
+        let invoices = house
+            .generate_invoices(bills, &nobody, &mut LargestRemainder)
+            .unwrap();
+        assert_eq!(
+            invoices.failed_bills(),
+            &[(
+                String::from("water"),
+                Error::Estimation(EstimationError::InsufficientHistory {
+                    required: 4,
+                    found: 0
+                })
+            )]
+        );
+        for invoice in invoices.invoices() {
+            assert_eq!(invoice.total, Money::of_major(USD, 500));
+        }
+    }
+
+    #[test]
+    fn failed_estimate_falls_back() {
+        let house: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+        let water = Bill::new(
+            Money::of_major(USD, 30),
+            DateInterval::from_strs("01/01/2020", "01/31/2020").unwrap(),
+        );
+        let bills = vec![(
+            "water",
+            SharingData::Variable(
+                (water, Covariates::new()),
+                vec![],
+                Box::new(OrdinaryLeastSquares),
+                EstimationConfig {
+                    fallback: EstimationFallback::SplitEvenly,
+                    ..EstimationConfig::default()
+                },
+            ),
+        )];
+        let invoices = house
+            .generate_invoices(bills, &nobody, &mut LargestRemainder)
+            .unwrap();
+        assert!(invoices.failed_bills().is_empty());
+        let (label, report) = &invoices.estimation_reports()[0];
+        assert_eq!(label, "water");
+        assert!(matches!(
+            report.rejection(),
+            Some(EstimationError::InsufficientHistory { .. })
+        ));
+        for invoice in invoices.invoices() {
+            assert_eq!(invoice.total, Money::of_major(USD, 15));
+        }
+    }
+
+    #[test]
+    fn whole_units_difference_to_house_fund() {
+        let house: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+        let bills = vec![
+            ("rent", fixed(Money::of_major(USD, 900))),
+            ("internet", fixed(Money::of_major(USD, 100))),
+        ];
+        let invoices = house
+            .generate_invoices(bills, &nobody, &mut WholeUnits)
+            .unwrap();
+        assert_eq!(
+            invoices.house_fund(),
+            &[(String::from("internet"), Money::of_minor(USD, -1_00))]
+        );
+        for invoice in invoices.invoices() {
+            assert_eq!(invoice.total, Money::of_major(USD, 333));
+            let adjustments = invoice.rounding_adjustments().collect::<Vec<_>>();
+            assert_eq!(adjustments.len(), 1);
+            let (label, adjustment) = adjustments[0];
+            assert_eq!(label, "internet");
+            assert_eq!(adjustment.amount(), Money::of_minor(USD, -33));
+            assert_eq!(adjustment.policy(), WholeUnits.name());
+        }
+    }
+
+    #[test]
+    fn adjustments_from_exact_shares() {
+        let house: RoommateGroup = vec!["a", "b", "c", "d", "e"].into_iter().collect();
+        let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+        // 20.8¢ each, so only the one who pays 20¢ rather than 21¢ was moved
+        // away from the nearest cent
+        let bills = vec![("internet", fixed(Money::of_minor(USD, 1_04)))];
+        let invoices = house
+            .generate_invoices(bills, &nobody, &mut LargestRemainder)
+            .unwrap();
+        let adjustments = invoices
+            .invoices()
+            .iter()
+            .flat_map(|invoice| invoice.rounding_adjustments())
+            .map(|(_, adjustment)| adjustment.amount())
+            .collect::<Vec<_>>();
+        assert_eq!(adjustments, vec![Money::of_minor(USD, -1)]);
+    }
+
+    #[test]
+    fn no_bills() {
+        let house: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+        let bills: Vec<(&str, SharingData<History>)> = vec![];
+        assert!(matches!(
+            house.generate_invoices(bills, &nobody, &mut LargestRemainder),
+            Err(Error::EmptyBillList)
+        ));
     }
 }
@@ -0,0 +1,551 @@
+use super::bill::Bill;
+use super::estimation::{
+    covariate_columns, Coefficient, Covariates, Estimate, EstimationModel, PREDICTION_LEVEL,
+};
+use super::interval::{DateInterval, ResponsibilityRecord};
+use super::regression::{regression_error, variance_inflation_factors, LeastSquares};
+use crate::{Error, EstimationError};
+
+/// Estimates the shared costs of several utilities' bills at once, with one
+/// occupancy effect for all of them
+///
+/// Each utility's daily usage-dependent cost is divided by its mean over the
+/// utility's history, so that utilities of any size can be compared, and the
+/// scaled costs of every utility are fit together by least squares:
+/// `Y / mean ~ Oc + utility + covariates`, per day. Each utility gets its own
+/// intercept and covariates, but occupancy moves every utility's cost by the
+/// same fraction of its mean. A utility with only a bill or two of history
+/// borrows the occupancy effect from the others, so lower
+/// [`EstimationConfig::min_history`] for it.
+///
+/// The shared cost of a utility's current bill is its fitted cost at zero
+/// occupancy, plus the bill's fixed cost. Use [`model`] to share each bill
+/// with [`SharedBill::from_estimate`] or in an invoice.
+///
+/// # Examples
+/// ```
+/// use roommates::{DateInterval, ResponsibilityRecord};
+/// use roommates::sharing::{Bill, Covariates, JointLeastSquares};
+/// use steel_cent::{Money, currency::USD};
+///
+/// let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+/// let month = |month| DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap();
+/// let bill = |amount, month| (Bill::new(Money::of_minor(USD, amount), month), Covariates::new());
+/// let electric = bill(60_00, month(3));
+/// let electric_history = vec![bill(58_00, month(1)), bill(62_00, month(2))];
+/// let water = bill(20_00, month(3));
+/// let water_history = vec![bill(20_00, month(2))];
+/// let joint = JointLeastSquares::fit(
+///     vec![
+///         ("electric", (&electric.0, &electric.1), &electric_history[..]),
+///         ("water", (&water.0, &water.1), &water_history[..]),
+///     ],
+///     &nobody,
+/// );
+/// // with nobody home there is no occupancy effect to fit
+/// assert!(joint.is_err());
+/// ```
+///
+/// [`EstimationConfig::min_history`]: struct.EstimationConfig.html#structfield.min_history
+/// [`model`]: struct.JointLeastSquares.html#method.model
+/// [`SharedBill::from_estimate`]: struct.SharedBill.html#method.from_estimate
+#[derive(Debug, Clone)]
+pub struct JointLeastSquares {
+    occupancy_effect: f64,
+    estimates: Vec<JointEstimate>,
+}
+
+/// The estimate that a [`JointLeastSquares`] fit made for one utility's
+/// current bill
+///
+/// As an [`EstimationModel`] it returns the estimate for that bill, and a
+/// [`Regression`] error for any other. It is also an error to give it an
+/// occupancy, covariates or history that differ from those that were fit,
+/// such as when [`EstimationConfig::tariff_change`] leaves out some of the
+/// history.
+///
+/// [`JointLeastSquares`]: struct.JointLeastSquares.html
+/// [`EstimationModel`]: trait.EstimationModel.html
+/// [`Regression`]: ../enum.EstimationError.html#variant.Regression
+/// [`EstimationConfig::tariff_change`]: struct.EstimationConfig.html#structfield.tariff_change
+#[derive(Debug, Clone)]
+pub struct JointEstimate {
+    label: String,
+    usage_period: DateInterval,
+    occupancy: u32,
+    covariates: Covariates,
+    /// Each history bill's usage period, occupancy and covariates
+    history: Vec<(DateInterval, u32, Covariates)>,
+    estimate: Estimate,
+}
+
+/// A utility's part of the joint fit
+struct Utility<'a> {
+    label: &'a str,
+    bill: &'a Bill,
+    occupancy: u32,
+    covariates: &'a Covariates,
+    /// Each history bill's usage period, occupancy and covariates
+    history: Vec<(DateInterval, u32, &'a Covariates)>,
+    /// Each history bill's usage-dependent cost per day, over `scale`
+    y: Vec<f64>,
+    /// The mean usage-dependent cost per day of the history bills
+    scale: f64,
+    covariate_columns: Vec<(String, bool, Vec<f64>)>,
+}
+
+impl JointLeastSquares {
+    /// Fits the bills of every utility together
+    ///
+    /// Each utility is given as a label, its current bill with its
+    /// covariates, and its history of earlier bills with their covariates.
+    /// Occupancy comes from `responsibility_intervals`.
+    ///
+    /// Returns a [`Regression`] error if a utility has no history or no
+    /// usage-dependent cost in it, or if the fit fails, a [`Collinear`]
+    /// error if occupancy or a covariate can't be told apart from the others,
+    /// and a [`DuplicateUtility`] error if two utilities have the same label.
+    ///
+    /// [`Regression`]: ../enum.EstimationError.html#variant.Regression
+    /// [`Collinear`]: ../enum.EstimationError.html#variant.Collinear
+    /// [`DuplicateUtility`]: ../enum.EstimationError.html#variant.DuplicateUtility
+    pub fn fit<'a, I>(
+        utilities: I,
+        responsibility_intervals: &ResponsibilityRecord,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<
+            Item = (
+                &'a str,
+                (&'a Bill, &'a Covariates),
+                &'a [(Bill, Covariates)],
+            ),
+        >,
+    {
+        let days = |period: DateInterval| period.num_days() as f64;
+        let utilities = utilities
+            .into_iter()
+            .map(|(label, (bill, covariates), history)| {
+                if history.is_empty() {
+                    return Err(regression_error(format!("no history of {} bills", label)));
+                }
+                let daily_cost = history
+                    .iter()
+                    .map(|(bill, _)| {
+                        (bill.amount_due().minor_amount() - bill.fixed_cost().minor_amount()) as f64
+                            / days(bill.usage_period())
+                    })
+                    .collect::<Vec<_>>();
+                let scale = daily_cost.iter().sum::<f64>() / daily_cost.len() as f64;
+                if scale.is_nan() || scale <= 0.0 {
+                    return Err(regression_error(format!(
+                        "no usage-dependent cost in the {} history",
+                        label
+                    )));
+                }
+                Ok(Utility {
+                    label,
+                    bill,
+                    occupancy: responsibility_intervals
+                        .checked_occupancy_over(bill.usage_period())?,
+                    covariates,
+                    history: history
+                        .iter()
+                        .map(|(bill, covariates)| {
+                            let period = bill.usage_period();
+                            Ok((
+                                period,
+                                responsibility_intervals.checked_occupancy_over(period)?,
+                                covariates,
+                            ))
+                        })
+                        .collect::<Result<_, Error>>()?,
+                    y: daily_cost.iter().map(|cost| cost / scale).collect(),
+                    scale,
+                    covariate_columns: covariate_columns(
+                        covariates,
+                        history.iter().map(|(_, covariates)| covariates),
+                    )?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        for (i, u) in utilities.iter().enumerate() {
+            if utilities[..i].iter().any(|other| other.label == u.label) {
+                return Err(Error::Estimation(EstimationError::DuplicateUtility {
+                    label: String::from(u.label),
+                }));
+            }
+        }
+
+        // occupancy, then an intercept for each utility after the first, then
+        // each utility's covariates, all per day
+        let mut names = vec![String::from("Oc")];
+        let mut columns = vec![utilities
+            .iter()
+            .flat_map(|u| {
+                u.history
+                    .iter()
+                    .map(|(period, oc, _)| f64::from(*oc) / days(*period))
+            })
+            .collect::<Vec<_>>()];
+        // a column that is `values` on the rows of utility `i` and zero
+        // elsewhere
+        let only = |i: usize, values: Vec<f64>| {
+            let mut column = vec![];
+            for (j, u) in utilities.iter().enumerate() {
+                if i == j {
+                    column.extend(&values);
+                } else {
+                    column.extend(vec![0.0; u.history.len()]);
+                }
+            }
+            column
+        };
+        for (i, u) in utilities.iter().enumerate().skip(1) {
+            names.push(String::from(u.label));
+            columns.push(only(i, vec![1.0; u.history.len()]));
+        }
+        let mut covariate_indices = vec![];
+        for (i, u) in utilities.iter().enumerate() {
+            let mut indices = vec![];
+            for (name, shared, column) in &u.covariate_columns {
+                indices.push((columns.len(), name, *shared));
+                names.push(format!("{}: {}", u.label, name));
+                let per_day = column
+                    .iter()
+                    .zip(&u.history)
+                    .map(|(value, (period, _, _))| value / days(*period))
+                    .collect();
+                columns.push(only(i, per_day));
+            }
+            covariate_indices.push(indices);
+        }
+        let y = utilities
+            .iter()
+            .flat_map(|u| u.y.iter().copied())
+            .collect::<Vec<_>>();
+        let variance_inflation = variance_inflation_factors(&columns, None);
+        if let Some((name, vif)) = names
+            .iter()
+            .zip(&variance_inflation)
+            .find(|(_, vif)| vif.is_some_and(f64::is_infinite))
+        {
+            return Err(Error::Estimation(EstimationError::Collinear {
+                regressor: name.clone(),
+                variance_inflation: vif.unwrap(),
+            }));
+        }
+        let fit = LeastSquares::fit(&y, &columns, None)?;
+
+        let coefficient = |name: &str, i: usize, scale: f64| Coefficient {
+            name: String::from(name),
+            value: fit.coefficients()[i + 1] * scale,
+            standard_error: fit.standard_errors()[i + 1] * scale,
+            p_value: fit.p_values()[i + 1],
+        };
+        let estimates = utilities
+            .iter()
+            .zip(covariate_indices)
+            .enumerate()
+            .map(|(i, (u, indices))| {
+                let days = days(u.bill.usage_period());
+                let fixed_cost = u.bill.fixed_cost().minor_amount() as f64;
+                let mut current = vec![0.0; columns.len()];
+                current[0] = f64::from(u.occupancy) / days;
+                if i > 0 {
+                    current[i] = 1.0;
+                }
+                let mut zero_occupancy = current.clone();
+                zero_occupancy[0] = 0.0;
+                let mut intercept = fit.coefficients()[0];
+                if i > 0 {
+                    intercept += fit.coefficients()[i + 1];
+                }
+                let mut contributions =
+                    vec![(String::from("intercept"), intercept * u.scale * days)];
+                let mut coefficients = vec![coefficient("Oc", 0, u.scale)];
+                let mut vifs = vec![];
+                for (index, name, shared) in indices {
+                    let value = u.covariates.get(name).map_or(0.0, |c| c.value()) / days;
+                    current[index] = value;
+                    coefficients.push(coefficient(name, index, u.scale));
+                    if shared {
+                        zero_occupancy[index] = value;
+                        let contribution = fit.coefficients()[index + 1] * value * u.scale * days;
+                        contributions.push((name.clone(), contribution));
+                    }
+                    if let Some(vif) = variance_inflation[index] {
+                        vifs.push((name.clone(), vif));
+                    }
+                }
+                contributions.push((String::from("fixed cost"), fixed_cost));
+                if let Some(vif) = variance_inflation[0] {
+                    vifs.insert(0, (String::from("Oc"), vif));
+                }
+                let (low, high) = fit.prediction_interval(&zero_occupancy, PREDICTION_LEVEL);
+                JointEstimate {
+                    label: String::from(u.label),
+                    usage_period: u.bill.usage_period(),
+                    occupancy: u.occupancy,
+                    covariates: u.covariates.clone(),
+                    history: u
+                        .history
+                        .iter()
+                        .map(|(period, oc, covariates)| (*period, *oc, (*covariates).clone()))
+                        .collect(),
+                    estimate: Estimate {
+                        shared_cost: contributions.iter().map(|(_, amount)| amount).sum(),
+                        rsquared: Some(fit.rsquared()),
+                        predicted: Some(fit.predict(&current) * u.scale * days + fixed_cost),
+                        coefficients,
+                        prediction_interval: Some((
+                            low * u.scale * days + fixed_cost,
+                            high * u.scale * days + fixed_cost,
+                        )),
+                        contributions,
+                        outliers: vec![],
+                        parameters: vec![
+                            (
+                                String::from("relative occupancy effect"),
+                                fit.coefficients()[1],
+                            ),
+                            (String::from("mean daily cost"), u.scale),
+                        ],
+                        variance_inflation: vifs,
+                    },
+                }
+            })
+            .collect();
+        Ok(JointLeastSquares {
+            occupancy_effect: fit.coefficients()[1],
+            estimates,
+        })
+    }
+
+    /// The fraction of a utility's mean daily cost that each person adds
+    pub fn occupancy_effect(&self) -> f64 {
+        self.occupancy_effect
+    }
+
+    /// The estimate for the current bill of the utility labeled `label`
+    pub fn model(&self, label: &str) -> Option<JointEstimate> {
+        self.estimates.iter().find(|e| e.label == label).cloned()
+    }
+}
+
+impl EstimationModel for JointEstimate {
+    fn name(&self) -> String {
+        format!("joint least squares per day ({})", self.label)
+    }
+
+    fn estimate(
+        &self,
+        bill: &Bill,
+        (occupancy, covariates): (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error> {
+        if bill.usage_period() != self.usage_period {
+            return Err(regression_error(format!(
+                "the joint fit was for the {} bill from {} to {}",
+                self.label,
+                self.usage_period.start(),
+                self.usage_period.end()
+            )));
+        }
+        let same_history = history.len() == self.history.len()
+            && history.iter().zip(&self.history).all(
+                |((bill, oc, covariates), (period, fit_oc, fit_covariates))| {
+                    bill.usage_period() == *period && oc == fit_oc && *covariates == fit_covariates
+                },
+            );
+        if occupancy != self.occupancy || *covariates != self.covariates || !same_history {
+            return Err(regression_error(format!(
+                "the {} bill or its history differs from the joint fit",
+                self.label
+            )));
+        }
+        Ok(self.estimate.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bill::SharedBill;
+    use crate::interval::ResponsibilityInterval;
+    use crate::roommate::RoommateGroup;
+    use crate::shared_cost::EstimationConfig;
+    use steel_cent::{currency::USD, Money};
+
+    /// How many people are home each month
+    const PEOPLE: [usize; 7] = [1, 2, 3, 1, 2, 3, 2];
+
+    fn month(month: u32) -> DateInterval {
+        DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap()
+    }
+
+    /// As many of `group`'s roommates home each month as `PEOPLE` says
+    fn monthly_record(group: &RoommateGroup) -> ResponsibilityRecord<'_> {
+        let mut roommates = group.iter().collect::<Vec<_>>();
+        roommates.sort();
+        (1..)
+            .zip(&PEOPLE)
+            .flat_map(|(m, people)| {
+                roommates[..*people]
+                    .iter()
+                    .map(move |roommate| ResponsibilityInterval::new(roommate, month(m), 0))
+            })
+            .collect()
+    }
+
+    /// A bill of `amount` minor units for month `m`, with no covariates
+    fn bill(amount: f64, m: u32) -> (Bill, Covariates) {
+        (
+            Bill::new(Money::of_minor(USD, amount as i64), month(m)),
+            Covariates::new(),
+        )
+    }
+
+    /// The number of people home in month `m`
+    fn people(m: u32) -> f64 {
+        PEOPLE[m as usize - 1] as f64
+    }
+
+    #[test]
+    fn sparse_utility_borrows_occupancy_effect() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let record = monthly_record(&group);
+        // $1 a day plus 50¢ a person-day, times each utility's size
+        let bill = |size: f64, m: u32| bill(size * 28.0 * (100.0 + 50.0 * people(m)), m);
+        let electric = bill(1.0, 7);
+        let electric_history = (1..=6).map(|m| bill(1.0, m)).collect::<Vec<_>>();
+        let gas = bill(2.0, 7);
+        let gas_history = (1..=6).map(|m| bill(2.0, m)).collect::<Vec<_>>();
+        // only two bills of water, from months with one and three people
+        let water = bill(0.5, 7);
+        let water_history = [bill(0.5, 4), bill(0.5, 6)];
+        let joint = JointLeastSquares::fit(
+            vec![
+                (
+                    "electric",
+                    (&electric.0, &electric.1),
+                    &electric_history[..],
+                ),
+                ("gas", (&gas.0, &gas.1), &gas_history[..]),
+                ("water", (&water.0, &water.1), &water_history[..]),
+            ],
+            &record,
+        )
+        .unwrap();
+        // 50¢ of the $2 mean daily cost
+        assert!((joint.occupancy_effect() - 0.25).abs() < 1e-9);
+
+        let model = joint.model("water").unwrap();
+        let config = EstimationConfig {
+            min_history: 2,
+            ..EstimationConfig::default()
+        };
+        let occupancy = |bill: &Bill| record.occupancy_over(bill.usage_period());
+        let history = water_history
+            .iter()
+            .map(|(bill, covariates)| (bill, occupancy(bill), covariates))
+            .collect::<Vec<_>>();
+        let notes = (occupancy(&water.0), water.1.clone());
+        let (shared_water, report) = SharedBill::from_estimate(
+            (water.0.clone(), notes.clone()),
+            history.clone(),
+            &model,
+            &config,
+        )
+        .unwrap();
+        assert!(report.rejection().is_none());
+        assert!(
+            (shared_water.shared_amount() - Money::of_minor(USD, 14_00))
+                .minor_amount()
+                .abs()
+                <= 1
+        );
+        assert!(report.marginal_cost().is_none());
+        let cost_per_person_day = report.estimate().unwrap().marginal_cost().unwrap();
+        assert!((cost_per_person_day - 25.0).abs() < 1e-9);
+
+        let other = bill(0.5, 6);
+        assert!(model.estimate(&other.0, (0, &other.1), &[]).is_err());
+        // the history and occupancy must be the ones that were fit
+        assert!(model
+            .estimate(&water.0, (notes.0, &notes.1), &history[1..])
+            .is_err());
+        assert!(model.estimate(&water.0, (0, &notes.1), &history).is_err());
+    }
+
+    #[test]
+    fn utilities_with_different_baselines() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let record = monthly_record(&group);
+        // a daily cost of `base` plus `per_person` a person-day
+        let bill =
+            |base: f64, per_person: f64, m: u32| bill(28.0 * (base + per_person * people(m)), m);
+        // in each utility's history, a person adds a quarter of its mean
+        // daily cost, but the baselines are half, a quarter and half of it
+        let electric_history = (1..=6).map(|m| bill(100.0, 50.0, m)).collect::<Vec<_>>();
+        let gas_history = [bill(200.0, 200.0, 3), bill(200.0, 200.0, 6)];
+        let water_history = [bill(50.0, 25.0, 4), bill(50.0, 25.0, 6)];
+        let (electric, gas, water) = (
+            bill(100.0, 50.0, 7),
+            bill(200.0, 200.0, 7),
+            bill(50.0, 25.0, 7),
+        );
+        let joint = JointLeastSquares::fit(
+            vec![
+                (
+                    "electric",
+                    (&electric.0, &electric.1),
+                    &electric_history[..],
+                ),
+                ("gas", (&gas.0, &gas.1), &gas_history[..]),
+                ("water", (&water.0, &water.1), &water_history[..]),
+            ],
+            &record,
+        )
+        .unwrap();
+        assert!((joint.occupancy_effect() - 0.25).abs() < 1e-9);
+        let occupancy = |bill: &Bill| record.occupancy_over(bill.usage_period());
+        for (label, (bill, covariates), history, shared_cost) in [
+            ("electric", &electric, &electric_history[..], 28_00.0),
+            ("gas", &gas, &gas_history[..], 56_00.0),
+            ("water", &water, &water_history[..], 14_00.0),
+        ] {
+            let history = history
+                .iter()
+                .map(|(bill, covariates)| (bill, occupancy(bill), covariates))
+                .collect::<Vec<_>>();
+            let estimate = joint
+                .model(label)
+                .unwrap()
+                .estimate(bill, (occupancy(bill), covariates), &history)
+                .unwrap();
+            assert!(
+                (estimate.shared_cost - shared_cost).abs() < 1e-6,
+                "{}: {}",
+                label,
+                estimate.shared_cost
+            );
+        }
+    }
+
+    #[test]
+    fn duplicate_labels() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let record = monthly_record(&group);
+        let bill = |m: u32| bill(28.0 * (100.0 + 50.0 * people(m)), m);
+        let current = bill(7);
+        let history = (1..=6).map(bill).collect::<Vec<_>>();
+        let utility = ("electric", (&current.0, &current.1), &history[..]);
+        assert_eq!(
+            JointLeastSquares::fit(vec![utility, utility], &record).unwrap_err(),
+            Error::Estimation(EstimationError::DuplicateUtility {
+                label: String::from("electric")
+            })
+        );
+    }
+}
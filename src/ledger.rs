@@ -0,0 +1,281 @@
+use num::{BigInt, BigRational, Signed, Zero};
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+use super::bill::SharedBill;
+use super::roommate::Roommate;
+use super::rounding::RoundingPolicy;
+
+/// A running record of how much rounding has favored or cost each roommate
+///
+/// The ledger is a [`RoundingPolicy`] that carries each roommate's
+/// fractional residue (exact share minus amount charged, in minor units)
+/// forward to the next bill. Residues are added to the next exact shares
+/// before rounding, so the extra cent does not keep going to the same
+/// roommate, and every residue stays strictly between -1 and 1 minor unit
+/// no matter how many bills are split. Shares always add up to the amount
+/// due.
+///
+/// The residues add up to zero, so when roommates who aren't part of a bill
+/// (such as roommates who have moved out) hold more than a unit between
+/// them, the roommates who are part of it settle the whole units, which are
+/// then cleared from the others' residues.
+///
+/// To keep the ledger between runs, save the residues from [`iter`] and
+/// collect them back into a `RoundingLedger` later.
+///
+/// [`RoundingPolicy`]: trait.RoundingPolicy.html
+/// [`iter`]: struct.RoundingLedger.html#method.iter
+///
+/// # Examples
+/// ```
+/// use roommates::{DateInterval, Roommate, RoommateGroup, ResponsibilityRecord};
+/// use roommates::sharing::{Bill, SharedBill};
+/// use roommates::splitting::RoundingLedger;
+/// use num::{One, Signed, Zero};
+/// use steel_cent::{Money, currency::USD};
+///
+/// let house: RoommateGroup = vec!["Bob", "Joe", "Sue"].into_iter().collect();
+/// let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+/// let mut ledger = RoundingLedger::new();
+/// for month in 1..=12 {
+///     let bill = SharedBill::from_fixed(Bill::new(
+///         Money::of_minor(USD, 100_00),
+///         DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap(),
+///     ));
+///     let split = house.individual_responsibilities(&nobody, bill.usage_period());
+///     house.split_bill_list(vec![(&bill, &split)], &mut ledger).unwrap();
+/// }
+/// for (_, residue) in ledger.iter() {
+///     assert!(residue.abs() < One::one());
+/// }
+/// assert!(ledger.residue(&Roommate::new("Bob")).is_zero());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RoundingLedger {
+    residues: HashMap<Roommate, BigRational>,
+}
+
+impl RoundingLedger {
+    /// Creates an empty `RoundingLedger`
+    pub fn new() -> Self {
+        RoundingLedger::default()
+    }
+
+    /// Returns how much less (in minor units) a roommate has been charged
+    /// than they exactly owe
+    ///
+    /// Negative if rounding has cost them more than it has saved them.
+    pub fn residue(&self, roommate: &Roommate) -> BigRational {
+        self.residues
+            .get(roommate)
+            .cloned()
+            .unwrap_or_else(BigRational::zero)
+    }
+
+    /// Returns an iterator over each roommate's residue
+    pub fn iter(&self) -> impl Iterator<Item = (&Roommate, &BigRational)> {
+        self.residues.iter()
+    }
+}
+
+impl RoundingPolicy for RoundingLedger {
+    fn name(&self) -> String {
+        String::from("carry-forward ledger")
+    }
+
+    fn round<'a>(
+        &mut self,
+        bill: &SharedBill,
+        exact_shares: HashMap<&'a Roommate, BigRational>,
+    ) -> HashMap<&'a Roommate, BigInt> {
+        if exact_shares.is_empty() {
+            return HashMap::new();
+        }
+        let settled = self.settle_absent(&exact_shares);
+        let count = BigRational::from_integer(exact_shares.len().into());
+        let mut shares = exact_shares
+            .into_iter()
+            .map(|(roommate, exact)| {
+                let owed = exact + self.residue(roommate) + &settled / &count;
+                let floor = owed.floor();
+                (roommate, owed.clone(), owed - &floor, floor.to_integer())
+            })
+            .collect::<Vec<_>>();
+        shares.sort_by(|(a, _, a_remainder, _), (b, _, b_remainder, _)| {
+            b_remainder.cmp(a_remainder).then_with(|| a.cmp(b))
+        });
+        // the owed amounts add up to the amount due plus less than a unit
+        // either way, so no share needs more than one unit over its floor
+        let mut leftover = BigInt::from(bill.amount_due().minor_amount())
+            - shares
+                .iter()
+                .map(|(_, _, _, charge)| charge)
+                .sum::<BigInt>();
+        for share in shares.iter_mut() {
+            if leftover > BigInt::zero() {
+                share.3 += 1;
+                leftover -= 1;
+            }
+        }
+        shares
+            .into_iter()
+            .map(|(roommate, owed, _, charge)| {
+                let residue = owed - BigRational::from_integer(charge.clone());
+                self.residues.insert(roommate.clone(), residue);
+                (roommate, charge)
+            })
+            .collect()
+    }
+}
+
+impl RoundingLedger {
+    /// Returns the whole units of the residues of the roommates who are part
+    /// of a bill, negated for them to settle, and clears as much of the same
+    /// from the residues of the roommates who aren't
+    fn settle_absent(&mut self, exact_shares: &HashMap<&Roommate, BigRational>) -> BigRational {
+        let settled = -exact_shares
+            .keys()
+            .fold(BigRational::zero(), |total, roommate| {
+                total + self.residue(roommate)
+            })
+            .trunc();
+        // take the units from the residues on the same side of zero, so
+        // that none of them crosses zero
+        let mut remaining = settled.clone();
+        for (_, residue) in self
+            .residues
+            .iter_mut()
+            .filter(|(roommate, _)| !exact_shares.contains_key(roommate))
+        {
+            if remaining.is_zero() {
+                break;
+            }
+            if residue.is_positive() == remaining.is_positive() && !residue.is_zero() {
+                let taken = if residue.abs() < remaining.abs() {
+                    residue.clone()
+                } else {
+                    remaining.clone()
+                };
+                *residue -= &taken;
+                remaining -= taken;
+            }
+        }
+        settled
+    }
+}
+
+impl FromIterator<(Roommate, BigRational)> for RoundingLedger {
+    fn from_iter<I: IntoIterator<Item = (Roommate, BigRational)>>(residues: I) -> Self {
+        RoundingLedger {
+            residues: residues.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bill::Bill;
+    use crate::interval::DateInterval;
+    use crate::roommate::RoommateGroup;
+    use crate::rounding::LargestRemainder;
+    use std::collections::HashSet;
+    use steel_cent::{currency::USD, Money};
+
+    fn monthly_bill(month: u32) -> SharedBill {
+        SharedBill::from_fixed(Bill::new(
+            Money::of_minor(USD, 1_00),
+            DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap(),
+        ))
+    }
+
+    fn ratio(numer: i64, denom: i64) -> BigRational {
+        BigRational::new(numer.into(), denom.into())
+    }
+
+    fn thirds(group: &RoommateGroup) -> HashMap<&Roommate, BigRational> {
+        group.iter().map(|r| (r, ratio(1_00, 3))).collect()
+    }
+
+    fn all_under_a_unit(ledger: &RoundingLedger) -> bool {
+        ledger.iter().all(|(_, r)| r.abs() < ratio(1, 1))
+    }
+
+    #[test]
+    fn extra_cent_moves_around() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let mut ledger = RoundingLedger::new();
+        let mut lucky = HashSet::new();
+        for month in 1..=12 {
+            let shares = ledger.round(&monthly_bill(month), thirds(&group));
+            assert_eq!(shares.values().sum::<BigInt>(), 1_00.into());
+            lucky.insert(shares.into_iter().find(|(_, s)| *s == 34.into()).unwrap().0);
+            assert!(all_under_a_unit(&ledger));
+        }
+        assert_eq!(lucky.len(), 3);
+        let always_same = (1..=12)
+            .map(|month| {
+                let shares = LargestRemainder.round(&monthly_bill(month), thirds(&group));
+                shares.into_iter().find(|(_, s)| *s == 34.into()).unwrap().0
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(always_same.len(), 1);
+    }
+
+    #[test]
+    fn absent_roommate_keeps_residue() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let a = group.borrow_by_name("a").unwrap();
+        let mut ledger: RoundingLedger = group.iter().map(|r| (r.clone(), ratio(2, 3))).collect();
+        let shares = ledger.round(
+            &monthly_bill(1),
+            group
+                .iter()
+                .filter(|r| *r != a)
+                .map(|r| (r, ratio(50, 1)))
+                .collect(),
+        );
+        assert_eq!(shares.values().sum::<BigInt>(), 1_00.into());
+        assert_eq!(ledger.residue(a), ratio(2, 3));
+        assert!(all_under_a_unit(&ledger));
+    }
+
+    #[test]
+    fn roommates_who_leave() {
+        let group: RoommateGroup = vec!["a", "b", "c", "d", "e", "f"].into_iter().collect();
+        let stayed = |r: &&Roommate| ["d", "e", "f"].contains(&r.to_string().as_str());
+        // a, b and c move out after being charged two thirds of a unit too
+        // little each, which d, e and f were charged too much
+        let mut ledger: RoundingLedger = group
+            .iter()
+            .map(|r| {
+                let residue = if stayed(&r) {
+                    ratio(-2, 3)
+                } else {
+                    ratio(2, 3)
+                };
+                (r.clone(), residue)
+            })
+            .collect();
+        let shares = ledger.round(
+            &monthly_bill(1),
+            group
+                .iter()
+                .filter(stayed)
+                .map(|r| (r, ratio(1_00, 3)))
+                .collect(),
+        );
+        assert_eq!(shares.values().sum::<BigInt>(), 1_00.into());
+        assert!(shares
+            .values()
+            .all(|share| *share == 33.into() || *share == 34.into()));
+        assert!(all_under_a_unit(&ledger));
+        assert_eq!(
+            ledger
+                .iter()
+                .fold(BigRational::zero(), |sum, (_, r)| sum + r),
+            BigRational::zero()
+        );
+    }
+}
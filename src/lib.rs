@@ -13,6 +13,7 @@
 //! ```
 //! use roommates::{
 //!     sharing::{Bill, SharedBill},
+//!     splitting::LargestRemainder,
 //!     ResponsibilityRecord,
 //!     ResponsibilityInterval,
 //!     DateInterval,
@@ -43,37 +44,74 @@
 //!     &responsibility,
 //!     water_bill.usage_period(),
 //! );
-//! let money_split = house.split_bill_list(vec![(&water_bill, &water_bill_split)]);
+//! let money_split = house.split_bill_list(
+//!     vec![(&water_bill, &water_bill_split)],
+//!     &mut LargestRemainder,
+//! ).unwrap();
 //! assert_eq!(
 //!     money_split.get(house.borrow_by_name("Bob").unwrap()).unwrap(),
 //!     &Money::of_minor(USD, 63_33),
 //! );
 //! assert_eq!(
 //!     money_split.get(house.borrow_by_name("Joe").unwrap()).unwrap(),
-//!     &Money::of_minor(USD, 36_66),
+//!     &Money::of_minor(USD, 36_67),
 //! );
 //! ```
 
+mod backtest;
 mod bill;
+mod degree_days;
+mod estimation;
+mod exchange;
+mod hourly;
+mod intensity;
 mod interval;
 mod invoice;
+mod joint;
+mod ledger;
+mod regression;
 mod roommate;
+mod rounding;
 mod shared_cost;
 mod split;
+mod tariff;
+#[cfg(test)]
+mod test_fixtures;
 
 pub use interval::{DateInterval, ResponsibilityInterval, ResponsibilityRecord};
 pub use roommate::{Roommate, RoommateGroup};
 pub mod sharing {
+    pub use super::backtest::{Backtest, BacktestComparison, BacktestStep};
     pub use super::bill::Bill;
     pub use super::bill::SharedBill;
+    pub use super::degree_days::{DailyTemperatures, DegreeDays};
+    pub use super::estimation::{
+        Coefficient, Covariate, Covariates, Estimate, EstimationModel, HuberLeastSquares,
+        OrdinaryLeastSquares, PerDayLeastSquares, SeasonalLeastSquares, Shrinkage,
+        WeightedLeastSquares,
+    };
+    pub use super::exchange::{CsvExchangeRates, ExchangeRateProvider};
+    pub use super::hourly::{
+        HourShare, HourlyMeterData, HourlyResponsibilityInterval, HourlyResponsibilityRecord,
+        HourlyShares,
+    };
     pub use super::invoice::SharingData;
+    pub use super::joint::{JointEstimate, JointLeastSquares};
+    pub use super::shared_cost::{EstimationConfig, EstimationFallback, EstimationReport, Pricing};
+    pub use super::tariff::{MeteredUsage, Tariff};
 }
 pub mod splitting {
-    pub use super::invoice::Invoice;
+    pub use super::intensity::{UsageIntensities, UsageIntensity};
+    pub use super::invoice::{Invoice, InvoiceComponent, Invoices, RoundingAdjustment};
+    pub use super::ledger::RoundingLedger;
+    pub use super::rounding::{
+        HalfEven, LargestRemainder, RemainderTo, Rotating, RoundingPolicy, WholeUnits,
+    };
     pub use super::split::ResponsibilitySplit;
 }
 
-use chrono::format::ParseError;
+use chrono::{format::ParseError, naive::NaiveDate};
+use steel_cent::{currency::Currency, Money};
 use thiserror::Error;
 
 #[derive(Debug, Error, PartialEq)]
@@ -89,6 +127,36 @@ pub enum Error {
 
     #[error(transparent)]
     InvalidFixedCost(InvalidFixedCost),
+
+    #[error("Arithmetic overflow while splitting costs")]
+    Overflow,
+
+    #[error("At least one bill is needed")]
+    EmptyBillList,
+
+    #[error("All bills must be in the same currency")]
+    MixedCurrencies,
+
+    #[error(
+        "Responsibility proportions must add up to 0 or 1 and only include roommates in the group"
+    )]
+    InvalidSplit,
+
+    #[error(transparent)]
+    Estimation(EstimationError),
+
+    #[error("No exchange rate from {} to {} on {date}", from.code(), to.code())]
+    MissingExchangeRate {
+        from: Currency,
+        to: Currency,
+        date: NaiveDate,
+    },
+
+    #[error("Invalid exchange rate data: {0}")]
+    InvalidExchangeRates(String),
+
+    #[error("Invalid meter data: {0}")]
+    InvalidMeterData(String),
 }
 
 #[derive(Debug, Error, PartialEq)]
@@ -102,3 +170,64 @@ pub enum InvalidFixedCost {
     #[error("Fixed cost cannot be negative")]
     Negative,
 }
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum EstimationError {
+    #[error("At least {required} bills of history are needed, found {found}")]
+    InsufficientHistory { required: usize, found: usize },
+
+    #[error("Shared cost model fits poorly (R² = {rsquared})")]
+    ModelFitsPoorly { rsquared: f64 },
+
+    #[error("Model poorly predicts most recent bill ({actual}) as {predicted}")]
+    PoorPrediction { actual: Money, predicted: Money },
+
+    #[error("Estimated shared cost ({estimate}) is not between zero and the amount due")]
+    SharedCostOutOfRange { estimate: Money },
+
+    #[error("Regression failed: {0}")]
+    Regression(String),
+
+    #[error("{regressor} varies together with the other regressors (variance inflation factor {variance_inflation})")]
+    Collinear {
+        regressor: String,
+        variance_inflation: f64,
+    },
+
+    #[error("No temperature recorded for {date}")]
+    MissingTemperature { date: NaiveDate },
+
+    #[error("{term} seem to lower the bill ({cost_per_degree_day} per degree day)")]
+    NegativeDegreeDayCost {
+        term: String,
+        cost_per_degree_day: f64,
+    },
+
+    #[error("The best balance temperature ({balance_temperature}) is at the edge of those tried")]
+    BalanceTemperatureAtEdge { balance_temperature: f64 },
+
+    #[error("{roommate}'s usage seems to cost nothing ({cost_per_person_day} per person-day)")]
+    NonPositiveIntensity {
+        roommate: String,
+        cost_per_person_day: f64,
+    },
+
+    #[error(
+        "The model does not estimate the marginal cost of occupancy or of an individual covariate"
+    )]
+    NoMarginalCost,
+
+    #[error("No metered usage recorded for the bill from {start} to {end}")]
+    MissingUsage { start: NaiveDate, end: NaiveDate },
+
+    #[error("The bill has no tariff to charge its usage by")]
+    MissingTariff,
+
+    #[error("More than one utility is labeled {label}")]
+    DuplicateUtility { label: String },
+
+    #[error(
+        "The half-life of a bill's weight must be a positive number of days, not {half_life_days}"
+    )]
+    InvalidHalfLife { half_life_days: f64 },
+}
@@ -0,0 +1,366 @@
+use nalgebra::{DMatrix, DVector};
+use statrs::distribution::{ContinuousCDF, StudentsT};
+
+use crate::{Error, EstimationError};
+
+/// A linear model with an intercept, fit by (weighted) least squares
+///
+/// Coefficients are in the order the regressors were given, after the
+/// intercept.
+pub(crate) struct LeastSquares {
+    coefficients: Vec<f64>,
+    standard_errors: Vec<f64>,
+    p_values: Vec<f64>,
+    rsquared: f64,
+    residuals: Vec<f64>,
+    /// (XᵀWX)⁻¹, which times `scale` is the covariance of the coefficients
+    unscaled_covariance: DMatrix<f64>,
+    /// The estimated variance of an observation with a weight of one
+    scale: f64,
+    degrees_of_freedom: usize,
+}
+
+impl LeastSquares {
+    /// Fits `y` against `regressors` (one column per regressor)
+    ///
+    /// `weights` default to one. Returns a [`Regression`] error if there are
+    /// no more observations than coefficients, or if the regressors are not
+    /// linearly independent.
+    ///
+    /// [`Regression`]: ../enum.EstimationError.html#variant.Regression
+    pub(crate) fn fit(
+        y: &[f64],
+        regressors: &[Vec<f64>],
+        weights: Option<&[f64]>,
+    ) -> Result<Self, Error> {
+        let n = y.len();
+        let p = regressors.len() + 1;
+        if n <= p {
+            return Err(regression_error(format!(
+                "{} observations are too few to fit {} coefficients",
+                n, p
+            )));
+        }
+        if regressors.iter().any(|column| column.len() != n)
+            || weights.is_some_and(|w| w.len() != n)
+        {
+            return Err(regression_error(String::from(
+                "every regressor needs one value per observation",
+            )));
+        }
+        if y.iter()
+            .chain(regressors.iter().flatten())
+            .chain(weights.into_iter().flatten())
+            .any(|value| !value.is_finite())
+        {
+            return Err(regression_error(String::from("data must be finite")));
+        }
+        let x = design_matrix(n, regressors);
+        let w = DVector::from_iterator(n, (0..n).map(|i| weights.map_or(1.0, |w| w[i])));
+        let yv = DVector::from_column_slice(y);
+        let xtw = weighted_transpose(&x, &w);
+        let unscaled_covariance = (&xtw * &x)
+            .try_inverse()
+            .ok_or_else(|| regression_error(String::from("regressors are collinear")))?;
+        let beta = &unscaled_covariance * (&xtw * &yv);
+        let residuals = &yv - &x * &beta;
+        let ssr = (0..n).map(|i| w[i] * residuals[i].powi(2)).sum::<f64>();
+        let total_weight = w.sum();
+        let mean = (0..n).map(|i| w[i] * y[i]).sum::<f64>() / total_weight;
+        let sst = (0..n).map(|i| w[i] * (y[i] - mean).powi(2)).sum::<f64>();
+        let rsquared = if sst > 0.0 {
+            1.0 - ssr / sst
+        } else if ssr <= f64::EPSILON {
+            1.0
+        } else {
+            0.0
+        };
+        let degrees_of_freedom = n - p;
+        let scale = ssr / degrees_of_freedom as f64;
+        let standard_errors = (0..p)
+            .map(|i| (scale * unscaled_covariance[(i, i)]).max(0.0).sqrt())
+            .collect::<Vec<_>>();
+        let t = StudentsT::new(0.0, 1.0, degrees_of_freedom as f64)
+            .expect("degrees of freedom are positive");
+        let p_values = beta
+            .iter()
+            .zip(&standard_errors)
+            .map(|(b, se)| {
+                if *se > 0.0 {
+                    2.0 * (1.0 - t.cdf((b / se).abs()))
+                } else if *b == 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        Ok(LeastSquares {
+            coefficients: beta.iter().copied().collect(),
+            standard_errors,
+            p_values,
+            rsquared,
+            residuals: residuals.iter().copied().collect(),
+            unscaled_covariance,
+            scale,
+            degrees_of_freedom,
+        })
+    }
+
+    /// Fits `y` against `regressors` by Huber M-estimation
+    ///
+    /// Observations whose residual is more than `tuning` robust standard
+    /// deviations from the fit are downweighted, so that a few outliers
+    /// cannot pull the fit towards themselves. Their Huber weights (between
+    /// zero and one) multiply any given `weights`. Returns the fit along with
+    /// the final Huber weight of each observation.
+    pub(crate) fn fit_huber(
+        y: &[f64],
+        regressors: &[Vec<f64>],
+        tuning: f64,
+        weights: Option<&[f64]>,
+    ) -> Result<(Self, Vec<f64>), Error> {
+        const MAX_ITERATIONS: usize = 100;
+        let mut huber_weights = vec![1.0; y.len()];
+        let mut fit = LeastSquares::fit(y, regressors, weights)?;
+        // residuals this small are rounding error, from most observations
+        // fitting exactly
+        let negligible = 1e-9 * y.iter().fold(1.0, |max, y| y.abs().max(max));
+        for _ in 0..MAX_ITERATIONS {
+            let scale = median_absolute_deviation(&fit.residuals) / 0.6745;
+            if scale <= negligible {
+                break;
+            }
+            let new_weights = fit
+                .residuals
+                .iter()
+                .map(|r| (tuning * scale / r.abs()).min(1.0))
+                .collect::<Vec<_>>();
+            let change = huber_weights
+                .iter()
+                .zip(&new_weights)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max);
+            huber_weights = new_weights;
+            let combined = huber_weights
+                .iter()
+                .enumerate()
+                .map(|(i, h)| h * weights.map_or(1.0, |w| w[i]))
+                .collect::<Vec<_>>();
+            fit = LeastSquares::fit(y, regressors, Some(&combined))?;
+            if change < 1e-6 {
+                break;
+            }
+        }
+        Ok((fit, huber_weights))
+    }
+
+    /// The intercept followed by the coefficient of each regressor
+    pub(crate) fn coefficients(&self) -> &[f64] {
+        &self.coefficients
+    }
+
+    pub(crate) fn standard_errors(&self) -> &[f64] {
+        &self.standard_errors
+    }
+
+    /// Two-sided p-values for each coefficient being zero
+    pub(crate) fn p_values(&self) -> &[f64] {
+        &self.p_values
+    }
+
+    /// The range that coefficient `i` (counting the intercept) falls in with
+    /// probability `level`
+    pub(crate) fn confidence_interval(&self, i: usize, level: f64) -> (f64, f64) {
+        let t = StudentsT::new(0.0, 1.0, self.degrees_of_freedom as f64)
+            .expect("degrees of freedom are positive");
+        let margin = t.inverse_cdf(0.5 + level / 2.0) * self.standard_errors[i];
+        (self.coefficients[i] - margin, self.coefficients[i] + margin)
+    }
+
+    pub(crate) fn rsquared(&self) -> f64 {
+        self.rsquared
+    }
+
+    /// The fitted value for one value of each regressor
+    pub(crate) fn predict(&self, regressors: &[f64]) -> f64 {
+        self.coefficients[0]
+            + self.coefficients[1..]
+                .iter()
+                .zip(regressors)
+                .map(|(b, x)| b * x)
+                .sum::<f64>()
+    }
+
+    /// The range that a new observation (with a weight of one) at
+    /// `regressors` falls in with probability `level`
+    pub(crate) fn prediction_interval(&self, regressors: &[f64], level: f64) -> (f64, f64) {
+        let x0 = DVector::from_iterator(
+            self.coefficients.len(),
+            std::iter::once(1.0).chain(regressors.iter().copied()),
+        );
+        let leverage = (x0.transpose() * &self.unscaled_covariance * &x0)[(0, 0)];
+        let spread = (self.scale * (1.0 + leverage)).max(0.0).sqrt();
+        let t = StudentsT::new(0.0, 1.0, self.degrees_of_freedom as f64)
+            .expect("degrees of freedom are positive");
+        let margin = t.inverse_cdf(0.5 + level / 2.0) * spread;
+        let fitted = self.predict(regressors);
+        (fitted - margin, fitted + margin)
+    }
+}
+
+/// How much collinearity with the other regressors inflates the variance of
+/// each regressor's coefficient, or `None` if that can't be measured
+///
+/// The factor for a regressor is 1 / (1 - R²) of the regressor fit against
+/// the others (with the same `weights` as the fit it belongs to), and is
+/// infinite if the others determine it exactly.
+pub(crate) fn variance_inflation_factors(
+    regressors: &[Vec<f64>],
+    weights: Option<&[f64]>,
+) -> Vec<Option<f64>> {
+    (0..regressors.len())
+        .map(|j| {
+            let others = regressors
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != j)
+                .map(|(_, column)| column.clone())
+                .collect::<Vec<_>>();
+            let rsquared = LeastSquares::fit(&regressors[j], &others, weights)
+                .ok()?
+                .rsquared();
+            // rounding error keeps an exact fit from reaching one
+            Some(if rsquared >= 1.0 - 1e-9 {
+                f64::INFINITY
+            } else {
+                1.0 / (1.0 - rsquared)
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn regression_error(message: String) -> Error {
+    Error::Estimation(EstimationError::Regression(message))
+}
+
+/// The median distance of the residuals from zero, which is where least
+/// squares centers them
+fn median_absolute_deviation(residuals: &[f64]) -> f64 {
+    let mut distances = residuals.iter().map(|r| r.abs()).collect::<Vec<_>>();
+    distances.sort_by(|a, b| a.partial_cmp(b).expect("residuals are finite"));
+    let mid = distances.len() / 2;
+    if distances.len() % 2 == 0 {
+        (distances[mid - 1] + distances[mid]) / 2.0
+    } else {
+        distances[mid]
+    }
+}
+
+/// A column of ones for the intercept followed by the regressors
+fn design_matrix(n: usize, regressors: &[Vec<f64>]) -> DMatrix<f64> {
+    DMatrix::from_fn(n, regressors.len() + 1, |i, j| {
+        if j == 0 {
+            1.0
+        } else {
+            regressors[j - 1][i]
+        }
+    })
+}
+
+/// Xᵀ with each column scaled by its observation's weight
+fn weighted_transpose(x: &DMatrix<f64>, w: &DVector<f64>) -> DMatrix<f64> {
+    let mut xtw = x.transpose();
+    for (j, mut column) in xtw.column_iter_mut().enumerate() {
+        column *= w[j];
+    }
+    xtw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn matches_known_fit() {
+        // y = 1.6 + 1.6x, with a residual sum of squares of 3.2
+        let x = vec![0.0, 1.0, 2.0, 3.0];
+        let y = vec![2.0, 2.0, 6.0, 6.0];
+        let fit = LeastSquares::fit(&y, &[x], None).unwrap();
+        assert!(close(fit.coefficients()[0], 1.6));
+        assert!(close(fit.coefficients()[1], 1.6));
+        assert!(close(fit.rsquared(), 0.8));
+        // s² = 3.2 / 2, (XᵀX)⁻¹ = [[0.7, -0.3], [-0.3, 0.2]]
+        assert!(close(fit.standard_errors()[1], (1.6f64 * 0.2).sqrt()));
+        assert!(fit.p_values()[1] > 0.05 && fit.p_values()[1] < 0.2);
+        let (low, high) = fit.prediction_interval(&[1.5], 0.95);
+        assert!(close((low + high) / 2.0, 4.0));
+        assert!(low < 0.0 && high > 7.0);
+    }
+
+    #[test]
+    fn weights() {
+        let x = vec![0.0, 1.0, 2.0, 3.0];
+        let y = vec![0.0, 1.0, 2.0, 100.0];
+        let fit = LeastSquares::fit(&y, &[x], Some(&[1.0, 1.0, 1.0, 0.0])).unwrap();
+        assert!(close(fit.coefficients()[0], 0.0));
+        assert!(close(fit.coefficients()[1], 1.0));
+    }
+
+    #[test]
+    fn huber_resists_outlier() {
+        let x = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let y = vec![1.1, 1.9, 3.0, 4.1, 30.0, 6.0, 6.9];
+        let ols = LeastSquares::fit(&y, std::slice::from_ref(&x), None).unwrap();
+        let (huber, weights) = LeastSquares::fit_huber(&y, &[x], 1.345, None).unwrap();
+        assert!((ols.coefficients()[1] - 1.0).abs() > 0.5);
+        assert!((huber.coefficients()[1] - 1.0).abs() < 0.1);
+        assert!(weights[4] < 0.1);
+        assert!(weights.iter().filter(|w| **w < 0.5).count() == 1);
+    }
+
+    #[test]
+    fn degenerate_data() {
+        let err = |result: Result<LeastSquares, Error>| {
+            matches!(
+                result,
+                Err(Error::Estimation(EstimationError::Regression(_)))
+            )
+        };
+        assert!(err(LeastSquares::fit(&[1.0, 2.0], &[vec![1.0, 2.0]], None)));
+        assert!(err(LeastSquares::fit(
+            &[1.0, 2.0, 3.0, 4.0],
+            &[vec![1.0, 2.0, 3.0, 4.0], vec![2.0, 4.0, 6.0, 8.0]],
+            None
+        )));
+        assert!(err(LeastSquares::fit(
+            &[1.0, 2.0, f64::NAN],
+            &[vec![1.0, 2.0, 3.0]],
+            None
+        )));
+    }
+
+    #[test]
+    fn variance_inflation() {
+        let x = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+        let unrelated = vec![1.0, -1.0, 0.0, -1.0, 1.0];
+        let doubled = x.iter().map(|x| 2.0 * x).collect::<Vec<_>>();
+        let factors = variance_inflation_factors(&[x.clone(), unrelated.clone()], None);
+        assert!(factors.iter().all(|vif| close(vif.unwrap(), 1.0)));
+        // only the last observation, which barely counts, tells x and y apart
+        let y = vec![1.0, 2.0, 3.0, 4.0, 10.0];
+        let weights = [1.0, 1.0, 1.0, 1.0, 1e-12];
+        let unweighted = variance_inflation_factors(&[x.clone(), y.clone()], None);
+        assert!(unweighted[0].unwrap() < 10.0);
+        let weighted = variance_inflation_factors(&[x.clone(), y], Some(&weights));
+        assert_eq!(weighted[0], Some(f64::INFINITY));
+        let factors = variance_inflation_factors(&[x, unrelated, doubled], None);
+        assert_eq!(factors[0], Some(f64::INFINITY));
+        assert_eq!(factors[2], Some(f64::INFINITY));
+        assert!(factors[1].is_none());
+    }
+}
@@ -5,7 +5,7 @@ use std::iter::FromIterator;
 /// Someone living in the housing unit
 ///
 /// A wrapper around the name of someone financially responsible for charges
-#[derive(Hash, PartialEq, Clone, Debug)]
+#[derive(Hash, PartialEq, PartialOrd, Ord, Clone, Debug)]
 pub struct Roommate(String);
 
 impl Roommate {
@@ -72,6 +72,6 @@ impl RoommateGroup {
 
 impl<'a> FromIterator<&'a str> for RoommateGroup {
     fn from_iter<I: IntoIterator<Item = &'a str>>(names: I) -> Self {
-        RoommateGroup(names.into_iter().map(|n| Roommate::new(n)).collect())
+        RoommateGroup(names.into_iter().map(Roommate::new).collect())
     }
 }
@@ -0,0 +1,361 @@
+use num::{BigInt, BigRational, Integer, One, Zero};
+use std::collections::HashMap;
+
+use super::bill::SharedBill;
+use super::roommate::Roommate;
+
+/// Decides how each roommate's exact share of a bill is turned into a whole
+/// number of minor units (e.g. cents)
+///
+/// Policies may keep state between calls, which lets them balance rounding
+/// across bills and billing cycles.
+pub trait RoundingPolicy {
+    /// A short description of the policy, recorded on invoices next to
+    /// every adjustment it makes
+    fn name(&self) -> String;
+
+    /// Takes the exact share (in minor units) that each roommate owes for
+    /// `bill` and returns the rounded amount that each roommate is charged
+    fn round<'a>(
+        &mut self,
+        bill: &SharedBill,
+        exact_shares: HashMap<&'a Roommate, BigRational>,
+    ) -> HashMap<&'a Roommate, BigInt>;
+}
+
+/// Rounds everyone down, then hands out the leftover units one at a time to
+/// whoever lost the most to rounding
+///
+/// Ties go to roommates in order of name. Shares always add up to the amount
+/// due.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestRemainder;
+
+impl RoundingPolicy for LargestRemainder {
+    fn name(&self) -> String {
+        String::from("largest remainder")
+    }
+
+    fn round<'a>(
+        &mut self,
+        bill: &SharedBill,
+        exact_shares: HashMap<&'a Roommate, BigRational>,
+    ) -> HashMap<&'a Roommate, BigInt> {
+        allocate(bill.amount_due().minor_amount().into(), exact_shares)
+    }
+}
+
+/// Rounds each share to the nearest unit on its own, with halves going to
+/// the even unit
+///
+/// Shares might not add up to the amount due; the difference is at most
+/// half a unit per roommate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HalfEven;
+
+impl RoundingPolicy for HalfEven {
+    fn name(&self) -> String {
+        String::from("round half to even")
+    }
+
+    fn round<'a>(
+        &mut self,
+        _bill: &SharedBill,
+        exact_shares: HashMap<&'a Roommate, BigRational>,
+    ) -> HashMap<&'a Roommate, BigInt> {
+        exact_shares
+            .into_iter()
+            .map(|(roommate, share)| (roommate, round_half_even(&share)))
+            .collect()
+    }
+}
+
+/// Rounds everyone down and gives all leftover units to one roommate
+///
+/// If the roommate is not part of the split, falls back to
+/// [`LargestRemainder`]. Shares always add up to the amount due.
+///
+/// [`LargestRemainder`]: struct.LargestRemainder.html
+#[derive(Debug, Clone)]
+pub struct RemainderTo(pub Roommate);
+
+impl RoundingPolicy for RemainderTo {
+    fn name(&self) -> String {
+        format!("remainder to {}", self.0)
+    }
+
+    fn round<'a>(
+        &mut self,
+        bill: &SharedBill,
+        exact_shares: HashMap<&'a Roommate, BigRational>,
+    ) -> HashMap<&'a Roommate, BigInt> {
+        if !exact_shares.contains_key(&self.0) {
+            return LargestRemainder.round(bill, exact_shares);
+        }
+        let (mut floors, leftover) = round_down(bill, exact_shares);
+        *floors.get_mut(&self.0).unwrap() += leftover;
+        floors
+    }
+}
+
+/// Rounds everyone down and hands out the leftover units in turn
+///
+/// Roommates take turns in order of name, and each bill picks up where the
+/// last one left off, so over several bills (such as electric and water in
+/// the same month) the leftover units go to everyone alike. Shares always
+/// add up to the amount due.
+///
+/// # Examples
+/// ```
+/// use roommates::{DateInterval, Roommate, RoommateGroup, ResponsibilityRecord};
+/// use roommates::sharing::{Bill, SharedBill};
+/// use roommates::splitting::Rotating;
+/// use steel_cent::{Money, currency::USD};
+///
+/// let house: RoommateGroup = vec!["Bob", "Joe", "Sue"].into_iter().collect();
+/// let nobody: ResponsibilityRecord = vec![].into_iter().collect();
+/// let mut rotating = Rotating::new();
+/// let cent_to = |split: std::collections::HashMap<&Roommate, Money>| {
+///     split.into_iter().find(|(_, charge)| charge.minor_amount() == 33_34).unwrap().0.clone()
+/// };
+/// let bill = SharedBill::from_fixed(Bill::new(
+///     Money::of_minor(USD, 100_00),
+///     DateInterval::new((2020, 1, 1), (2020, 1, 31)).unwrap(),
+/// ));
+/// let split = house.individual_responsibilities(&nobody, bill.usage_period());
+/// let electric = house.split_bill_list(vec![(&bill, &split)], &mut rotating).unwrap();
+/// let water = house.split_bill_list(vec![(&bill, &split)], &mut rotating).unwrap();
+/// assert_eq!(cent_to(electric), Roommate::new("Bob"));
+/// assert_eq!(cent_to(water), Roommate::new("Joe"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Rotating {
+    last: Option<Roommate>,
+}
+
+impl Rotating {
+    /// Creates a `Rotating` policy that starts with the first roommate by
+    /// name
+    pub fn new() -> Self {
+        Rotating::default()
+    }
+}
+
+impl RoundingPolicy for Rotating {
+    fn name(&self) -> String {
+        String::from("rotating remainder")
+    }
+
+    fn round<'a>(
+        &mut self,
+        bill: &SharedBill,
+        exact_shares: HashMap<&'a Roommate, BigRational>,
+    ) -> HashMap<&'a Roommate, BigInt> {
+        let (mut floors, mut leftover) = round_down(bill, exact_shares);
+        let mut order = floors.keys().copied().collect::<Vec<_>>();
+        order.sort();
+        if order.is_empty() {
+            return floors;
+        }
+        // the roommate after the last one to get a unit, even if they aren't
+        // part of this bill
+        let mut index = self.last.as_ref().map_or(0, |last| {
+            order
+                .iter()
+                .position(|roommate| *roommate > last)
+                .unwrap_or(0)
+        });
+        while leftover > BigInt::zero() {
+            *floors.get_mut(order[index]).unwrap() += 1;
+            self.last = Some(order[index].clone());
+            leftover -= 1;
+            index = (index + 1) % order.len();
+        }
+        floors
+    }
+}
+
+/// Rounds each share to the nearest whole major unit (e.g. dollar)
+///
+/// Shares will usually not add up to the amount due; the difference is meant
+/// to go into (or come out of) a house fund, and invoices list it in
+/// [`Invoices::house_fund`].
+///
+/// [`Invoices::house_fund`]: struct.Invoices.html#method.house_fund
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WholeUnits;
+
+impl RoundingPolicy for WholeUnits {
+    fn name(&self) -> String {
+        String::from("whole units, difference to house fund")
+    }
+
+    fn round<'a>(
+        &mut self,
+        bill: &SharedBill,
+        exact_shares: HashMap<&'a Roommate, BigRational>,
+    ) -> HashMap<&'a Roommate, BigInt> {
+        let unit = BigInt::from(10).pow(bill.amount_due().currency.decimal_places() as u32);
+        exact_shares
+            .into_iter()
+            .map(|(roommate, share)| {
+                let units = (share / &unit).round().to_integer();
+                (roommate, units * &unit)
+            })
+            .collect()
+    }
+}
+
+/// Divides `total` minor units according to `exact_shares` (largest remainder)
+///
+/// Every share is rounded down, and then the leftover units are handed out
+/// one at a time, starting with the share that lost the most to rounding.
+/// Ties go to roommates in order of name, so the result is deterministic.
+/// The returned shares add up to `total` as long as the exact shares do.
+fn allocate(
+    total: BigInt,
+    exact_shares: HashMap<&Roommate, BigRational>,
+) -> HashMap<&Roommate, BigInt> {
+    let mut shares = exact_shares
+        .into_iter()
+        .map(|(roommate, share)| {
+            let floor = share.floor();
+            (roommate, floor.to_integer(), share - floor)
+        })
+        .collect::<Vec<_>>();
+    let mut leftover = total - shares.iter().map(|(_, floor, _)| floor).sum::<BigInt>();
+    shares.sort_by(|(a, _, a_remainder), (b, _, b_remainder)| {
+        b_remainder.cmp(a_remainder).then_with(|| a.cmp(b))
+    });
+    shares
+        .into_iter()
+        .map(|(roommate, floor, _)| {
+            if leftover > BigInt::zero() {
+                leftover -= 1;
+                (roommate, floor + 1)
+            } else {
+                (roommate, floor)
+            }
+        })
+        .collect()
+}
+
+/// Rounds every share down and returns the number of units left over
+fn round_down<'a>(
+    bill: &SharedBill,
+    exact_shares: HashMap<&'a Roommate, BigRational>,
+) -> (HashMap<&'a Roommate, BigInt>, BigInt) {
+    let floors = exact_shares
+        .into_iter()
+        .map(|(roommate, share)| (roommate, share.floor().to_integer()))
+        .collect::<HashMap<_, _>>();
+    let leftover = BigInt::from(bill.amount_due().minor_amount()) - floors.values().sum::<BigInt>();
+    (floors, leftover)
+}
+
+fn round_half_even(share: &BigRational) -> BigInt {
+    let floor = share.floor();
+    let remainder = share - &floor;
+    let floor = floor.to_integer();
+    let half = BigRational::new(One::one(), 2.into());
+    if remainder > half || (remainder == half && floor.is_odd()) {
+        floor + 1
+    } else {
+        floor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bill::Bill;
+    use crate::interval::DateInterval;
+    use crate::roommate::RoommateGroup;
+    use steel_cent::{currency::USD, Money};
+
+    fn new_bill(total: i64) -> SharedBill {
+        SharedBill::from_fixed(Bill::new(
+            Money::of_minor(USD, total),
+            DateInterval::new((2020, 1, 1), (2020, 12, 31)).unwrap(),
+        ))
+    }
+
+    fn ratio(numer: i64, denom: i64) -> BigRational {
+        BigRational::new(numer.into(), denom.into())
+    }
+
+    fn thirds(group: &RoommateGroup) -> HashMap<&Roommate, BigRational> {
+        group.iter().map(|r| (r, ratio(100, 3))).collect()
+    }
+
+    fn get(group: &RoommateGroup, shares: &HashMap<&Roommate, BigInt>, name: &str) -> BigInt {
+        shares
+            .get(group.borrow_by_name(name).unwrap())
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn remainder_to_named_roommate() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let bill = new_bill(1_00);
+        let shares = RemainderTo(Roommate::new("b")).round(&bill, thirds(&group));
+        assert_eq!(get(&group, &shares, "a"), 33.into());
+        assert_eq!(get(&group, &shares, "b"), 34.into());
+        assert_eq!(get(&group, &shares, "c"), 33.into());
+    }
+
+    #[test]
+    fn rotating_remainder_changes_with_each_bill() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let mut rotating = Rotating::new();
+        let winners = (0..3)
+            .map(|_| {
+                let shares = rotating.round(&new_bill(1_00), thirds(&group));
+                assert_eq!(shares.values().sum::<BigInt>(), 1_00.into());
+                shares
+                    .into_iter()
+                    .find(|(_, share)| *share == 34.into())
+                    .unwrap()
+                    .0
+                    .clone()
+            })
+            .collect::<Vec<_>>();
+        assert_ne!(winners[0], winners[1]);
+        assert_ne!(winners[1], winners[2]);
+        assert_ne!(winners[0], winners[2]);
+    }
+
+    #[test]
+    fn rotating_remainder_skips_roommates_not_in_the_bill() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let mut rotating = Rotating {
+            last: Some(Roommate::new("b")),
+        };
+        let pair = group
+            .iter()
+            .filter(|r| **r != Roommate::new("c"))
+            .map(|r| (r, ratio(101, 2)))
+            .collect();
+        let shares = rotating.round(&new_bill(1_01), pair);
+        assert_eq!(get(&group, &shares, "a"), 51.into());
+        assert_eq!(rotating.last, Some(Roommate::new("a")));
+    }
+
+    #[test]
+    fn half_even() {
+        assert_eq!(round_half_even(&ratio(5, 2)), 2.into());
+        assert_eq!(round_half_even(&ratio(7, 2)), 4.into());
+        assert_eq!(round_half_even(&ratio(10, 3)), 3.into());
+        assert_eq!(round_half_even(&ratio(11, 3)), 4.into());
+    }
+
+    #[test]
+    fn whole_units() {
+        let group: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
+        let bill = new_bill(10_000);
+        let exact = group.iter().map(|r| (r, ratio(10_000, 3))).collect();
+        let shares = WholeUnits.round(&bill, exact);
+        assert!(shares.values().all(|share| *share == 3_300.into()));
+    }
+}
@@ -1,49 +1,296 @@
-use linregress::{FormulaRegressionBuilder, RegressionDataBuilder, RegressionModel};
-use std::collections::HashMap;
-use std::error::Error;
-use steel_cent::Money;
+use chrono::naive::NaiveDate;
+use std::fmt;
+use steel_cent::{currency::Currency, Money};
 
 use super::bill::{Bill, SharedBill};
+use super::estimation::{Covariates, Estimate, EstimationModel};
+use crate::{Error, EstimationError};
+
+/// What to do with a bill whose shared cost cannot be estimated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EstimationFallback {
+    /// Share only the bill's `fixed_cost`, as with [`SharedBill::from_fixed`]
+    ///
+    /// [`SharedBill::from_fixed`]: struct.SharedBill.html#method.from_fixed
+    FixedCost,
+    /// Share the whole bill evenly
+    SplitEvenly,
+    /// Return the estimation error
+    Fail,
+}
+
+/// How an accepted estimate divides a bill between what is shared and what
+/// is split by occupancy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pricing {
+    /// Share the estimated shared cost and split the rest by occupancy
+    SharedCost,
+    /// Split the model's fitted cost of occupancy and of individual
+    /// covariates by occupancy, and share whatever remains
+    ///
+    /// Each person-day, including guests', then carries the fitted marginal
+    /// cost of occupancy rather than an average share of the usage.
+    /// Individual covariates have no owner, so their fitted cost is split by
+    /// occupancy along with the rest rather than charged to one roommate.
+    MarginalCost,
+}
+
+/// When to accept an estimated shared cost, and what to do otherwise
+///
+/// The defaults need four bills of history, an R² of at least 0.80, a
+/// prediction of the current bill within 20% of its amount due, and
+/// regressors that can be told apart. The estimated shared cost is shared,
+/// clamped between zero and the amount due, failed estimates are returned as
+/// errors, and all of the history is used.
+///
+/// # Examples
+/// ```
+/// use roommates::DateInterval;
+/// use roommates::sharing::{
+///     Bill, Covariates, EstimationConfig, EstimationFallback, OrdinaryLeastSquares, SharedBill,
+/// };
+/// use steel_cent::{Money, currency::USD};
+///
+/// let water_bill = Bill::new_with_fixed_cost(
+///     Money::of_minor(USD, 83_22),
+///     DateInterval::new((2020, 4, 15), (2020, 5, 15)).unwrap(),
+///     Money::of_minor(USD, 10_00),
+/// ).unwrap();
+/// let config = EstimationConfig {
+///     min_rsquared: 0.6,
+///     fallback: EstimationFallback::FixedCost,
+///     ..EstimationConfig::default()
+/// };
+/// let (water_bill, report) = SharedBill::from_estimate(
+///     (water_bill, (2, Covariates::new())),
+///     vec![],
+///     &OrdinaryLeastSquares,
+///     &config,
+/// ).unwrap();
+/// assert_eq!(water_bill.shared_amount(), Money::of_minor(USD, 10_00));
+/// assert!(report.rejection().is_some());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EstimationConfig {
+    /// The fewest history bills to fit a model to, unless the model handles
+    /// short histories itself (like [`Shrinkage`])
+    ///
+    /// [`Shrinkage`]: struct.Shrinkage.html
+    pub min_history: usize,
+    /// The lowest R² of a model fit to the history that is accepted
+    pub min_rsquared: f64,
+    /// The largest accepted |actual - predicted| / actual for the current
+    /// bill
+    pub max_prediction_error: f64,
+    /// The largest accepted variance inflation factor of a regressor, past
+    /// which the model can't tell its effect apart from the others'
+    ///
+    /// The default of `f64::INFINITY` only rejects regressors that can't be
+    /// told apart at all. A limit such as 10 also rejects regressors that
+    /// vary closely together, such as occupancy and the weather.
+    pub max_variance_inflation: f64,
+    /// If set, history bills whose usage period starts before this date (such
+    /// as when the utility changed its rates) are left out
+    pub tariff_change: Option<NaiveDate>,
+    /// How to divide the bill once an estimate is accepted
+    pub pricing: Pricing,
+    /// Whether an estimate below zero or above the amount due is moved into
+    /// range (`true`) or rejected (`false`)
+    pub clamp: bool,
+    /// What to do if the estimate is not accepted
+    pub fallback: EstimationFallback,
+}
+
+impl Default for EstimationConfig {
+    fn default() -> Self {
+        EstimationConfig {
+            // the fewest bills that a model with an intercept and two
+            // covariates can be fit to with a residual left over
+            min_history: 4,
+            min_rsquared: 0.80,
+            max_prediction_error: 0.2,
+            max_variance_inflation: f64::INFINITY,
+            tariff_change: None,
+            pricing: Pricing::SharedCost,
+            clamp: true,
+            fallback: EstimationFallback::Fail,
+        }
+    }
+}
+
+/// How the shared cost of a bill was estimated, and how confident the
+/// estimate is
+///
+/// If the estimate was rejected and a fallback was used instead, the report
+/// says why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EstimationReport {
+    model: String,
+    currency: Currency,
+    estimate: Option<Estimate>,
+    marginal_cost: Option<f64>,
+    rejection: Option<EstimationError>,
+}
+
+impl EstimationReport {
+    /// The name of the model used
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// The model's estimate and diagnostics, unless the model could not make
+    /// one
+    pub fn estimate(&self) -> Option<&Estimate> {
+        self.estimate.as_ref()
+    }
+
+    /// The cost (in minor units) charged per person-day, if the bill was
+    /// priced at marginal cost
+    pub fn marginal_cost(&self) -> Option<f64> {
+        self.marginal_cost
+    }
+
+    /// Why the estimate was not used, if the fallback was used instead
+    pub fn rejection(&self) -> Option<&EstimationError> {
+        self.rejection.as_ref()
+    }
+}
+
+impl fmt::Display for EstimationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let money = |minor: f64| Money::of_minor(self.currency, minor.round() as i64);
+        write!(f, "estimated by {}", self.model)?;
+        if let Some(estimate) = &self.estimate {
+            write!(f, ": {} shared", money(estimate.shared_cost))?;
+            if let Some((low, high)) = estimate.prediction_interval {
+                write!(
+                    f,
+                    " (95% prediction interval {} to {})",
+                    money(low),
+                    money(high)
+                )?;
+            }
+            if let Some(rsquared) = estimate.rsquared {
+                write!(f, "\n\tR² = {:.3}", rsquared)?;
+            }
+            if let Some(marginal_cost) = self.marginal_cost {
+                write!(f, "\n\t{} charged per person-day", money(marginal_cost))?;
+            }
+            for (name, value) in &estimate.parameters {
+                write!(f, "\n\t{} = {:.3}", name, value)?;
+            }
+            for c in &estimate.coefficients {
+                write!(
+                    f,
+                    "\n\t{} = {:.3} (standard error {:.3}, p = {:.3})",
+                    c.name, c.value, c.standard_error, c.p_value
+                )?;
+            }
+            for (name, vif) in &estimate.variance_inflation {
+                write!(f, "\n\tvariance inflation of {} = {:.3}", name, vif)?;
+            }
+            for (name, amount) in &estimate.contributions {
+                write!(f, "\n\t{} contributes {}", name, money(*amount))?;
+            }
+            for period in &estimate.outliers {
+                write!(
+                    f,
+                    "\n\toutlier: bill from {} to {}",
+                    period.start(),
+                    period.end()
+                )?;
+            }
+        }
+        if let Some(rejection) = &self.rejection {
+            write!(f, "\n\tnot used: {}", rejection)?;
+        }
+        Ok(())
+    }
+}
 
 impl SharedBill {
-    /// builds a linear model to predict amount due based on bill history,
-    /// then applies the model to the current month, setting the
-    /// occupancy to zero
+    /// Estimates the shared cost of a bill with `model`, based on the bill
+    /// history, and checks the estimate against `config`
+    ///
+    /// Returns the bill along with a report of the estimate and its
+    /// diagnostics.
     ///
-    /// Performs poorly if temperature index and occupancy always vary
-    /// together
+    /// You will need several bills worth of data for this to work, unless the
+    /// model handles short histories itself. If there is too little history,
+    /// the model fits the history poorly, it poorly predicts the current bill,
+    /// or it can't tell apart regressors that vary together (all according to
+    /// `config`), the configured fallback is used. With [`EstimationFallback::Fail`] an [`Estimation`] error is
+    /// returned instead. History from before `config.tariff_change` doesn't
+    /// count.
     ///
-    /// You will need several bills worth of data for this to work
+    /// With [`Pricing::MarginalCost`] the shared amount is what remains after
+    /// charging `occupancy` person-days at the model's marginal cost and the
+    /// individual covariates at their fitted cost, and a model without those
+    /// costs is rejected.
+    ///
+    /// [`EstimationFallback::Fail`]: enum.EstimationFallback.html#variant.Fail
+    /// [`Estimation`]: ../enum.Error.html#variant.Estimation
+    /// [`Pricing::MarginalCost`]: enum.Pricing.html#variant.MarginalCost
     pub fn from_estimate<'a, I>(
-        bill_with_notes: (Bill, (u32, Option<f64>)),
+        (bill, (occupancy, covariates)): (Bill, (u32, Covariates)),
         bill_history: I,
-    ) -> Result<Self, Box<dyn Error>>
+        model: &dyn EstimationModel,
+        config: &EstimationConfig,
+    ) -> Result<(Self, EstimationReport), Error>
     where
-        I: IntoIterator<Item = (&'a Bill, u32, Option<f64>)>,
+        I: IntoIterator<Item = (&'a Bill, u32, &'a Covariates)>,
     {
-        let (y, oc, ti) = extract_variables(bill_history);
-        let data: Vec<(&str, Vec<_>)> = vec![("Y", y), ("Oc", oc), ("TI", ti)];
-        let data = RegressionDataBuilder::new().build_from(data)?;
-        let formula = "Y ~ Oc + TI";
-        let model = FormulaRegressionBuilder::new()
-            .data(&data)
-            .formula(formula)
-            .fit()?;
-        let (bill, notes) = bill_with_notes;
-        bill.assess_model(&model, notes);
-        assert!(
-            model.rsquared >= 0.80,
-            format!("shared cost model fits poorly ({})", &model.rsquared)
-        );
-        let intercept_value = model.parameters.intercept_value;
-        let parameters: HashMap<_, _> = model.parameters.pairs().into_iter().collect();
-        let shared_cost = 0.0f64.max((bill.amount_due().minor_amount() as f64).min(
-            intercept_value
-                + *parameters.get("TI").unwrap() * notes.1.unwrap_or(0.0)
-                + bill.fixed_cost().minor_amount() as f64,
-        ));
-        let currency = bill.amount_due().currency;
-        Ok(SharedBill::new(bill, Money::of_minor(currency, shared_cost as i64)).unwrap())
+        let history = bill_history
+            .into_iter()
+            .filter(|(bill, _, _)| match config.tariff_change {
+                Some(date) => bill.usage_period().start() >= date,
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        let (estimate, shared_cost) = if history.len() < config.min_history
+            && !model.handles_short_history()
+        {
+            let error = EstimationError::InsufficientHistory {
+                required: config.min_history,
+                found: history.len(),
+            };
+            (None, Err(Error::Estimation(error)))
+        } else {
+            match model.estimate(&bill, (occupancy, &covariates), &history) {
+                Ok(estimate) => {
+                    let shared_cost = accept(&bill, (occupancy, &covariates), &estimate, config);
+                    (Some(estimate), shared_cost)
+                }
+                Err(error) => (None, Err(error)),
+            }
+        };
+        let marginal_cost = match (&estimate, config.pricing) {
+            (Some(estimate), Pricing::MarginalCost) => estimate.marginal_cost(),
+            _ => None,
+        };
+        let report = |rejection: Option<EstimationError>| EstimationReport {
+            model: model.name(),
+            currency: bill.amount_due().currency,
+            estimate,
+            marginal_cost: marginal_cost.filter(|_| rejection.is_none()),
+            rejection,
+        };
+        match (shared_cost, config.fallback) {
+            (Ok(shared_cost), _) => {
+                let report = report(None);
+                Ok((SharedBill::new(bill, shared_cost)?, report))
+            }
+            (Err(Error::Estimation(error)), EstimationFallback::FixedCost) => {
+                let report = report(Some(error));
+                Ok((SharedBill::from_fixed(bill), report))
+            }
+            (Err(Error::Estimation(error)), EstimationFallback::SplitEvenly) => {
+                let report = report(Some(error));
+                let amount_due = bill.amount_due();
+                Ok((SharedBill::new(bill, amount_due)?, report))
+            }
+            (Err(error), _) => Err(error),
+        }
     }
 
     pub fn from_fixed(bill: Bill) -> Self {
@@ -51,57 +298,79 @@ impl SharedBill {
         SharedBill::new(bill, shared_amount).expect("invalid fixed cost")
     }
 }
-impl Bill {
-    /// |actual - predicted| / actual
-    ///
-    /// closer to zero is better
-    fn assess_model(&self, model: &RegressionModel, notes: (u32, Option<f64>)) -> f64 {
-        let (oc, ti) = notes;
-        let data = vec![("Oc", vec![oc as f64]), ("TI", vec![ti.unwrap_or(0.0)])];
-        let predicted = model.predict(data).unwrap()[0];
-        let actual = self.amount_due().minor_amount() as f64;
-        let error = (predicted - actual).abs() / actual;
-        assert!(
-            error <= 0.2,
-            format!(
-                "model poorly predicts most recent bill ({}) as {}",
-                actual, predicted,
-            )
-        );
-        error
-    }
-}
 
-fn extract_variables<'a, I>(bill_history: I) -> (Vec<f64>, Vec<f64>, Vec<f64>)
-where
-    I: IntoIterator<Item = (&'a Bill, u32, Option<f64>)>,
-{
-    let (cost_oc, ti): (Vec<_>, Vec<_>) = bill_history
-        .into_iter()
-        .map(|(bill, occupancy, temperature_index)| {
-            (
-                (
-                    (bill.amount_due().minor_amount() - bill.fixed_cost().minor_amount()) as f64,
-                    occupancy as f64,
-                ),
-                temperature_index.unwrap_or(0.0),
-            )
-        })
-        .unzip();
-    let (cost, oc) = cost_oc.into_iter().unzip();
-    (cost, oc, ti)
+/// Checks an estimate against `config`, returning the shared cost
+fn accept(
+    bill: &Bill,
+    (occupancy, covariates): (u32, &Covariates),
+    estimate: &Estimate,
+    config: &EstimationConfig,
+) -> Result<Money, Error> {
+    let currency = bill.amount_due().currency;
+    let amount_due = bill.amount_due().minor_amount() as f64;
+    if let Some((regressor, vif)) = estimate
+        .variance_inflation
+        .iter()
+        .find(|(_, vif)| vif.is_nan() || *vif > config.max_variance_inflation)
+    {
+        return Err(Error::Estimation(EstimationError::Collinear {
+            regressor: regressor.clone(),
+            variance_inflation: *vif,
+        }));
+    }
+    if let Some(predicted) = estimate.predicted {
+        // compared without dividing so that $0 bills don't divide by zero
+        let error = (predicted - amount_due).abs();
+        if error.is_nan() || error > config.max_prediction_error * amount_due.abs() {
+            return Err(Error::Estimation(EstimationError::PoorPrediction {
+                actual: bill.amount_due(),
+                predicted: Money::of_minor(currency, predicted.round() as i64),
+            }));
+        }
+    }
+    if let Some(rsquared) = estimate.rsquared {
+        if rsquared.is_nan() || rsquared < config.min_rsquared {
+            return Err(Error::Estimation(EstimationError::ModelFitsPoorly {
+                rsquared,
+            }));
+        }
+    }
+    let estimated = match config.pricing {
+        Pricing::SharedCost => estimate.shared_cost,
+        Pricing::MarginalCost => {
+            let marginal_cost = estimate
+                .marginal_cost()
+                .ok_or(Error::Estimation(EstimationError::NoMarginalCost))?;
+            let individual_cost = estimate
+                .individual_cost(covariates)
+                .ok_or(Error::Estimation(EstimationError::NoMarginalCost))?;
+            amount_due - marginal_cost * occupancy as f64 - individual_cost
+        }
+    };
+    let shared_cost = if config.clamp {
+        0.0f64.max(amount_due.min(estimated))
+    } else {
+        estimated
+    };
+    // a credit has no range to clamp into, so it goes to the fallback too
+    if !(0.0..=amount_due).contains(&shared_cost) {
+        return Err(Error::Estimation(EstimationError::SharedCostOutOfRange {
+            estimate: Money::of_minor(currency, estimated.round() as i64),
+        }));
+    }
+    Ok(Money::of_minor(currency, shared_cost as i64))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::estimation::{Covariate, OrdinaryLeastSquares, Shrinkage};
     use crate::interval::DateInterval;
     use steel_cent::currency::USD;
 
-    fn build_bills(
-        history: Vec<(i64, u32)>,
-        current: (i64, u32),
-    ) -> (Vec<(Bill, u32, Option<f64>)>, Bill, (u32, Option<f64>)) {
+    type BillsWithNotes = (Vec<(Bill, u32, Covariates)>, Bill, (u32, Covariates));
+
+    fn build_bills(history: Vec<(i64, u32)>, current: (i64, u32)) -> BillsWithNotes {
         let bills = history
             .into_iter()
             .map(|(m, oc)| {
@@ -111,7 +380,7 @@ mod tests {
                         DateInterval::from_strs("01/01/2020", "02/01/2020").unwrap(),
                     ),
                     oc,
-                    None,
+                    Covariates::new(),
                 )
             })
             .collect::<Vec<_>>();
@@ -119,42 +388,32 @@ mod tests {
             Money::of_minor(USD, current.0),
             DateInterval::from_strs("04/01/2020", "05/01/2020").unwrap(),
         );
-        let notes = (current.1, None);
+        let notes = (current.1, Covariates::new());
         (bills, current_bill, notes)
     }
 
+    fn temperature_index(ti: f64) -> Covariates {
+        std::iter::once((String::from("TI"), Covariate::Shared(ti))).collect()
+    }
+
     fn build_bills_ti(
         history: Vec<(i64, u32)>,
         current: (i64, u32, f64),
         ti: Vec<f64>,
-    ) -> (Vec<(Bill, u32, Option<f64>)>, Bill, (u32, Option<f64>)) {
+    ) -> BillsWithNotes {
         assert_eq!(history.len(), ti.len());
         assert!(history.len() > 1);
         let (bills, current_bill, notes) = build_bills(history, (current.0, current.1));
         let (oc, _) = notes;
-        let notes = (oc, Some(current.2));
+        let notes = (oc, temperature_index(current.2));
         let history = bills
             .into_iter()
-            .zip(ti.into_iter())
-            .map(|((bill, oc, _), ti)| (bill, oc, Some(ti)))
+            .zip(ti)
+            .map(|((bill, oc, _), ti)| (bill, oc, temperature_index(ti)))
             .collect::<Vec<_>>();
         (history, current_bill, notes)
     }
 
-    #[test]
-    fn get_variables_from_simple_bill_history() {
-        let (bills, _, _) = build_bills(vec![(20_00, 1), (30_00, 2), (40_00, 3)], (50_00, 0));
-        let (y, x1, x2) = extract_variables(
-            bills
-                .iter()
-                .map(|(b, oc, ti)| (b, *oc, *ti))
-                .collect::<Vec<_>>(),
-        );
-        assert_eq!(y, vec![2000.0, 3000.0, 4000.0]);
-        assert_eq!(x1, vec![1.0, 2.0, 3.0]);
-        assert_eq!(x2, vec![0.0, 0.0, 0.0]);
-    }
-
     #[test]
     fn simple_bill_history_shared_cost() {
         let (bills, current, notes) = build_bills(
@@ -165,13 +424,31 @@ mod tests {
             (current, notes),
             bills
                 .iter()
-                .map(|(b, oc, ti)| (b, *oc, *ti))
+                .map(|(b, oc, ti)| (b, *oc, ti))
                 .collect::<Vec<_>>(),
+            &OrdinaryLeastSquares,
+            &EstimationConfig::default(),
         )
-        .unwrap();
+        .unwrap()
+        .0;
         assert!((current.shared_amount() - Money::of_minor(USD, 10_00)) <= Money::of_minor(USD, 1));
     }
 
+    #[test]
+    fn shrinkage_with_two_bills() {
+        let (bills, current, notes) = build_bills(vec![(10_00, 0), (20_00, 1)], (50_00, 4));
+        let (current, report) = SharedBill::from_estimate(
+            (current, notes),
+            bills.iter().map(|(b, oc, c)| (b, *oc, c)),
+            &Shrinkage::new(0.6),
+            &EstimationConfig::default(),
+        )
+        .unwrap();
+        // too few bills to fit, so 60% of the bill is shared
+        assert!(report.rejection().is_none());
+        assert_eq!(current.shared_amount(), Money::of_minor(USD, 30_00));
+    }
+
     #[test]
     fn bill_history_with_temperature() {
         let (bills, current, notes) = build_bills_ti(
@@ -183,68 +460,368 @@ mod tests {
             (current, notes),
             bills
                 .iter()
-                .map(|(b, oc, ti)| (b, *oc, *ti))
+                .map(|(b, oc, ti)| (b, *oc, ti))
                 .collect::<Vec<_>>(),
+            &OrdinaryLeastSquares,
+            &EstimationConfig::default(),
         )
-        .unwrap();
+        .unwrap()
+        .0;
         assert_eq!(current.shared_amount(), Money::of_minor(USD, 11_00));
     }
 
     #[test]
     fn bill_with_fixed_cost() {
-        let bill_history = vec![
+        let bill_history = [
             (
                 Bill::new_with_fixed_cost(
-                    Money::of_minor(USD, 110_00),
+                    Money::of_major(USD, 110),
                     DateInterval::from_strs("01/01/2001", "02/02/2002").unwrap(),
-                    Money::of_minor(USD, 100_00),
+                    Money::of_major(USD, 100),
                 )
                 .unwrap(),
                 0,
-                None,
+                Covariates::new(),
             ),
             (
                 Bill::new_with_fixed_cost(
-                    Money::of_minor(USD, 120_00),
+                    Money::of_major(USD, 120),
                     DateInterval::from_strs("01/01/2001", "02/02/2002").unwrap(),
-                    Money::of_minor(USD, 100_00),
+                    Money::of_major(USD, 100),
                 )
                 .unwrap(),
                 1,
-                None,
+                Covariates::new(),
             ),
             (
                 Bill::new(
-                    Money::of_minor(USD, 30_00),
+                    Money::of_major(USD, 30),
                     DateInterval::from_strs("01/01/2001", "02/02/2002").unwrap(),
                 ),
                 2,
-                None,
+                Covariates::new(),
             ),
             (
                 Bill::new(
-                    Money::of_minor(USD, 40_00),
+                    Money::of_major(USD, 40),
                     DateInterval::from_strs("01/01/2001", "02/02/2002").unwrap(),
                 ),
                 3,
-                None,
+                Covariates::new(),
             ),
         ];
         let bill = Bill::new_with_fixed_cost(
-            Money::of_minor(USD, 50_00),
+            Money::of_major(USD, 50),
             DateInterval::from_strs("01/01/2001", "02/02/2002").unwrap(),
-            Money::of_minor(USD, 10_00),
+            Money::of_major(USD, 10),
         )
         .unwrap();
-        let notes = (4, None);
+        let notes = (4, Covariates::new());
         let current = SharedBill::from_estimate(
             (bill, notes),
             bill_history
                 .iter()
-                .map(|(b, oc, ti)| (b, *oc, *ti))
+                .map(|(b, oc, ti)| (b, *oc, ti))
                 .collect::<Vec<_>>(),
+            &OrdinaryLeastSquares,
+            &EstimationConfig::default(),
+        )
+        .unwrap()
+        .0;
+        assert!((current.shared_amount() - Money::of_major(USD, 20)) <= Money::of_minor(USD, 1));
+    }
+
+    fn estimate_with(
+        bills: &BillsWithNotes,
+        config: &EstimationConfig,
+    ) -> Result<SharedBill, Error> {
+        let (history, current, notes) = bills;
+        SharedBill::from_estimate(
+            (current.clone(), notes.clone()),
+            history.iter().map(|(b, oc, ti)| (b, *oc, ti)),
+            &OrdinaryLeastSquares,
+            config,
+        )
+        .map(|(bill, _)| bill)
+    }
+
+    fn estimate(bills: &BillsWithNotes) -> Result<SharedBill, Error> {
+        estimate_with(bills, &EstimationConfig::default())
+    }
+
+    #[test]
+    fn too_little_history() {
+        let bills = build_bills(vec![(10_00, 0), (20_00, 1)], (30_00, 2));
+        assert_eq!(
+            estimate(&bills).err(),
+            Some(Error::Estimation(EstimationError::InsufficientHistory {
+                required: 4,
+                found: 2
+            }))
+        );
+    }
+
+    #[test]
+    fn poor_prediction() {
+        let bills = build_bills(
+            vec![(10_00, 0), (20_00, 1), (30_00, 2), (40_00, 3)],
+            (90_00, 4),
+        );
+        assert_eq!(
+            estimate(&bills).err(),
+            Some(Error::Estimation(EstimationError::PoorPrediction {
+                actual: Money::of_minor(USD, 90_00),
+                predicted: Money::of_minor(USD, 50_00),
+            }))
+        );
+    }
+
+    #[test]
+    fn zero_dollar_bill() {
+        let bills = build_bills(vec![(10_00, 0), (20_00, 1), (30_00, 2), (40_00, 3)], (0, 0));
+        assert!(matches!(
+            estimate(&bills),
+            Err(Error::Estimation(EstimationError::PoorPrediction { .. }))
+        ));
+    }
+
+    #[test]
+    fn model_fits_poorly() {
+        let bills = build_bills(
+            vec![(10_00, 0), (40_00, 1), (10_00, 2), (40_00, 3), (25_00, 4)],
+            (25_00, 2),
+        );
+        assert!(matches!(
+            estimate(&bills),
+            Err(Error::Estimation(EstimationError::ModelFitsPoorly { .. }))
+        ));
+    }
+
+    #[test]
+    fn occupancy_and_temperature_vary_together() {
+        let exactly = build_bills_ti(
+            vec![(20_00, 1), (30_00, 2), (40_00, 3), (50_00, 4), (60_00, 5)],
+            (70_00, 6, 12.0),
+            vec![2.0, 4.0, 6.0, 8.0, 10.0],
+        );
+        assert!(matches!(
+            estimate(&exactly),
+            Err(Error::Estimation(EstimationError::Collinear { variance_inflation, .. }))
+                if variance_inflation.is_infinite()
+        ));
+        let nearly = build_bills_ti(
+            vec![(20_00, 1), (30_00, 2), (40_00, 3), (50_00, 4), (60_00, 5)],
+            (70_00, 6, 12.0),
+            vec![2.0, 4.5, 6.0, 7.5, 10.0],
+        );
+        // r² between them is 361 / 365, which only a configured limit rejects
+        let checked = EstimationConfig {
+            max_variance_inflation: 10.0,
+            ..EstimationConfig::default()
+        };
+        assert!(matches!(
+            estimate_with(&nearly, &checked),
+            Err(Error::Estimation(EstimationError::Collinear { variance_inflation, .. }))
+                if (variance_inflation - 365.0 / 4.0).abs() < 1e-6
+        ));
+        let bill = estimate(&nearly).unwrap();
+        assert_eq!(bill.shared_amount(), Money::of_minor(USD, 10_00));
+    }
+
+    #[test]
+    fn fallbacks() {
+        let bills = build_bills(vec![(10_00, 0)], (30_00, 2));
+        let with = |fallback| {
+            estimate_with(
+                &bills,
+                &EstimationConfig {
+                    fallback,
+                    ..EstimationConfig::default()
+                },
+            )
+        };
+        assert_eq!(
+            with(EstimationFallback::FixedCost).unwrap().shared_amount(),
+            Money::zero(USD)
+        );
+        assert_eq!(
+            with(EstimationFallback::SplitEvenly)
+                .unwrap()
+                .shared_amount(),
+            Money::of_minor(USD, 30_00)
+        );
+        assert!(with(EstimationFallback::Fail).is_err());
+    }
+
+    #[test]
+    fn configured_thresholds() {
+        let bills = build_bills(
+            vec![(10_00, 0), (20_00, 1), (30_00, 2), (40_00, 3)],
+            (58_00, 4),
+        );
+        assert!(estimate(&bills).is_ok());
+        let strict = EstimationConfig {
+            max_prediction_error: 0.1,
+            ..EstimationConfig::default()
+        };
+        assert!(matches!(
+            estimate_with(&bills, &strict),
+            Err(Error::Estimation(EstimationError::PoorPrediction { .. }))
+        ));
+        let more_history = EstimationConfig {
+            min_history: 5,
+            ..EstimationConfig::default()
+        };
+        assert!(matches!(
+            estimate_with(&bills, &more_history),
+            Err(Error::Estimation(
+                EstimationError::InsufficientHistory { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn marginal_cost_pricing() {
+        // $10 plus $10 per person-day, with $8 more this time
+        let (history, current, notes) = build_bills(
+            vec![(10_00, 0), (20_00, 1), (30_00, 2), (40_00, 3)],
+            (58_00, 4),
+        );
+        let marginal = EstimationConfig {
+            pricing: Pricing::MarginalCost,
+            ..EstimationConfig::default()
+        };
+        let (bill, report) = SharedBill::from_estimate(
+            (current, notes),
+            history.iter().map(|(b, oc, ti)| (b, *oc, ti)),
+            &OrdinaryLeastSquares,
+            &marginal,
+        )
+        .unwrap();
+        assert!((report.marginal_cost().unwrap() - 10_00.0).abs() < 1e-6);
+        assert_eq!(bill.shared_amount(), Money::of_minor(USD, 18_00));
+        assert!(report.to_string().contains("charged per person-day"));
+    }
+
+    #[test]
+    fn marginal_cost_pricing_charges_individual_covariates() {
+        // $10 plus $5 per person-day and $2 per load of laundry, with $3 more
+        // this time
+        let loads = [3.0, 1.0, 4.0, 1.0, 5.0, 9.0];
+        let (history, current, (occupancy, _)) = build_bills(
+            (1..)
+                .zip(&loads)
+                .map(|(oc, loads)| (10_00 + 5_00 * oc + 2_00 * *loads as i64, oc as u32))
+                .collect(),
+            (31_00, 2),
+        );
+        let laundry = |loads| {
+            std::iter::once((String::from("laundry"), Covariate::Individual(loads))).collect()
+        };
+        let history = history
+            .into_iter()
+            .zip(&loads)
+            .map(|((bill, oc, _), loads)| (bill, oc, laundry(*loads)))
+            .collect::<Vec<(Bill, u32, Covariates)>>();
+        let marginal = EstimationConfig {
+            pricing: Pricing::MarginalCost,
+            ..EstimationConfig::default()
+        };
+        let (bill, _) = SharedBill::from_estimate(
+            (current, (occupancy, laundry(4.0))),
+            history.iter().map(|(b, oc, notes)| (b, *oc, notes)),
+            &OrdinaryLeastSquares,
+            &marginal,
+        )
+        .unwrap();
+        // $10 for two person-days and $8 for four loads
+        assert_eq!(bill.shared_amount(), Money::of_minor(USD, 13_00));
+    }
+
+    #[test]
+    fn tariff_change_drops_old_history() {
+        let bills = build_bills(
+            vec![(10_00, 0), (20_00, 1), (30_00, 2), (40_00, 3)],
+            (50_00, 4),
+        );
+        let after_history = EstimationConfig {
+            tariff_change: NaiveDate::from_ymd_opt(2020, 1, 2),
+            ..EstimationConfig::default()
+        };
+        assert_eq!(
+            estimate_with(&bills, &after_history).err(),
+            Some(Error::Estimation(EstimationError::InsufficientHistory {
+                required: 4,
+                found: 0
+            }))
+        );
+        let before_history = EstimationConfig {
+            tariff_change: NaiveDate::from_ymd_opt(2020, 1, 1),
+            ..EstimationConfig::default()
+        };
+        assert!(estimate_with(&bills, &before_history).is_ok());
+    }
+
+    #[test]
+    fn unclamped_estimate_out_of_range() {
+        // the history implies a $10 shared cost, more than this $5 bill
+        let bills = build_bills(
+            vec![(10_00, 0), (20_00, 1), (30_00, 2), (40_00, 3)],
+            (5_00, 0),
+        );
+        let unclamped = EstimationConfig {
+            clamp: false,
+            max_prediction_error: 1.0,
+            ..EstimationConfig::default()
+        };
+        assert!(matches!(
+            estimate_with(&bills, &unclamped),
+            Err(Error::Estimation(
+                EstimationError::SharedCostOutOfRange { .. }
+            ))
+        ));
+        let clamped = EstimationConfig {
+            max_prediction_error: 1.0,
+            ..EstimationConfig::default()
+        };
+        assert_eq!(
+            estimate_with(&bills, &clamped).unwrap().shared_amount(),
+            Money::of_minor(USD, 5_00)
+        );
+    }
+
+    #[test]
+    fn report() {
+        let (bills, current, notes) = build_bills(
+            vec![(10_00, 0), (21_00, 1), (29_00, 2), (40_00, 3), (50_00, 4)],
+            (60_00, 5),
+        );
+        let (current, report) = SharedBill::from_estimate(
+            (current, notes),
+            bills.iter().map(|(b, oc, ti)| (b, *oc, ti)),
+            &OrdinaryLeastSquares,
+            &EstimationConfig::default(),
         )
         .unwrap();
-        assert!((current.shared_amount() - Money::of_minor(USD, 20_00)) <= Money::of_minor(USD, 1));
+        assert_eq!(report.model(), "ordinary least squares");
+        assert!(report.rejection().is_none());
+        let estimate = report.estimate().unwrap();
+        let names = estimate
+            .coefficients
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["intercept", "Oc"]);
+        assert!(estimate.coefficients[1].p_value < 0.001);
+        let (low, high) = estimate.prediction_interval.unwrap();
+        let shared = current.shared_amount().minor_amount() as f64;
+        assert!(low < shared && shared < high);
+        let total = estimate
+            .contributions
+            .iter()
+            .map(|(_, amount)| amount)
+            .sum::<f64>();
+        assert!((total - estimate.shared_cost).abs() < 1e-6);
+        assert!(report.to_string().contains("95% prediction interval"));
     }
 }
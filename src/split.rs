@@ -1,28 +1,50 @@
-use num::rational::Ratio;
+use num::{rational::Ratio, BigInt, BigRational, One, ToPrimitive, Zero};
 use std::collections::HashMap;
 use steel_cent::Money;
 
 use super::bill::SharedBill;
 use super::interval::{DateInterval, ResponsibilityRecord};
 use super::roommate::{Roommate, RoommateGroup};
+use super::rounding::RoundingPolicy;
+use super::Error;
 
 impl RoommateGroup {
     /// Returns the proportion of the total cost that each contributing party
     /// is responsible for
+    ///
+    /// # Panics
+    /// Panics if the occupancy does not fit in a `u32`; see
+    /// [`checked_individual_responsibilities`] for an error instead.
+    ///
+    /// [`checked_individual_responsibilities`]: struct.RoommateGroup.html#method.checked_individual_responsibilities
     pub fn individual_responsibilities(
         &self,
         responsibility_intervals: &ResponsibilityRecord,
         billing_period: DateInterval,
-    ) -> ResponsibilitySplit {
-        let map: HashMap<&Roommate, Ratio<u32>> = self
+    ) -> ResponsibilitySplit<'_> {
+        self.checked_individual_responsibilities(responsibility_intervals, billing_period)
+            .expect("occupancy does not fit in a u32")
+    }
+
+    /// Like [`individual_responsibilities`], but returns an [`Overflow`]
+    /// error if the occupancy cannot be counted
+    ///
+    /// [`individual_responsibilities`]: struct.RoommateGroup.html#method.individual_responsibilities
+    /// [`Overflow`]: enum.Error.html#variant.Overflow
+    pub fn checked_individual_responsibilities(
+        &self,
+        responsibility_intervals: &ResponsibilityRecord,
+        billing_period: DateInterval,
+    ) -> Result<ResponsibilitySplit<'_>, Error> {
+        let map = self
             .iter()
             .map(|roommate| {
-                (
+                Ok((
                     roommate,
-                    responsibility_intervals.roommate_responsibility(roommate, billing_period),
-                )
+                    responsibility_intervals.roommate_responsibility(roommate, billing_period)?,
+                ))
             })
-            .collect();
+            .collect::<Result<HashMap<&Roommate, Ratio<u32>>, Error>>()?;
         self.build_split(map)
     }
 
@@ -31,85 +53,126 @@ impl RoommateGroup {
     /// how much each roommate is personally responsible for and then outputs
     /// a new HashMap that accumulates all
     ///
-    /// ## Panics
-    /// Panics if bills are not all in the same currency.
-    /// Panics if the list is empty.
+    /// Each bill is split exactly and then rounded with `rounding`. With
+    /// [`LargestRemainder`] the shares of every bill (and so of the whole
+    /// list) always add up to the amount due.
+    ///
+    /// Returns an [`EmptyBillList`] error if there are no bills, a
+    /// [`MixedCurrencies`] error if the bills are not all in the same
+    /// currency, and an [`Overflow`] error if a share does not fit in a
+    /// `Money`.
+    ///
+    /// [`LargestRemainder`]: splitting/struct.LargestRemainder.html
+    /// [`EmptyBillList`]: enum.Error.html#variant.EmptyBillList
+    /// [`MixedCurrencies`]: enum.Error.html#variant.MixedCurrencies
+    /// [`Overflow`]: enum.Error.html#variant.Overflow
     pub fn split_bill_list<'a, I>(
         &self,
         bills_with_usage_proportions: I,
-    ) -> HashMap<&'a Roommate, Money>
+        rounding: &mut dyn RoundingPolicy,
+    ) -> Result<HashMap<&'a Roommate, Money>, Error>
     where
         I: IntoIterator<Item = (&'a SharedBill, &'a ResponsibilitySplit<'a>)>,
     {
         let mut bills_with_usage_proportions = bills_with_usage_proportions.into_iter().peekable();
         let currency = bills_with_usage_proportions
             .peek()
-            .expect("must include at least one bill")
+            .ok_or(Error::EmptyBillList)?
             .0
             .amount_due()
             .currency;
 
-        bills_with_usage_proportions
-            .inspect(|(bill, _)| {
-                assert!(
-                    bill.amount_due().currency == currency,
-                    "all bills must have the same currency"
-                )
-            })
-            .map(|(bill, usage_proportion)| self.split(bill, usage_proportion).into_iter())
-            .flatten()
-            .fold(HashMap::new(), |mut m, (k, v)| {
-                let val = m.entry(k).or_insert_with(|| Money::zero(currency));
-                *val = *val + v;
-                m
-            })
+        let mut totals = HashMap::new();
+        for (bill, usage_proportion) in bills_with_usage_proportions {
+            if bill.amount_due().currency != currency {
+                return Err(Error::MixedCurrencies);
+            }
+            for (roommate, share) in self.split(bill, usage_proportion, rounding)? {
+                let total = totals
+                    .entry(roommate)
+                    .or_insert_with(|| Money::zero(currency));
+                *total = total.checked_add(share).ok_or(Error::Overflow)?;
+            }
+        }
+        Ok(totals)
     }
 
-    fn split<'a>(
+    pub(crate) fn split<'a>(
         &self,
         bill: &SharedBill,
         usage_proportion: &'a ResponsibilitySplit,
-    ) -> HashMap<&'a Roommate, Money> {
-        usage_proportion
+        rounding: &mut dyn RoundingPolicy,
+    ) -> Result<HashMap<&'a Roommate, Money>, Error> {
+        let currency = bill.amount_due().currency;
+        let exact_shares = usage_proportion
             .hash_map()
             .into_iter()
-            .map(|(roommate, share)| (roommate, self.divide(bill, share)))
+            .map(|(roommate, share)| (roommate, self.exact_share(bill, &share)))
+            .collect();
+        rounding
+            .round(bill, exact_shares)
+            .into_iter()
+            .map(|(roommate, minor)| {
+                let minor = minor.to_i64().ok_or(Error::Overflow)?;
+                Ok((roommate, Money::of_minor(currency, minor)))
+            })
             .collect()
     }
 
-    fn divide(&self, bill: &SharedBill, personally_responsible: Ratio<u32>) -> Money {
-        (Money::of_minor(
-            bill.amount_due().currency,
-            bill.shared_amount().minor_amount(),
-        ) / self.count() as i64)
-            + Money::of_minor(
-                bill.amount_due().currency,
-                (bill.amount_due() - bill.shared_amount()).minor_amount(),
-            )
-            .mul_rational(personally_responsible)
+    /// The unrounded amount (in minor units) that someone who is
+    /// `personally_responsible` for a portion of the bill owes
+    pub(crate) fn exact_share(
+        &self,
+        bill: &SharedBill,
+        personally_responsible: &BigRational,
+    ) -> BigRational {
+        let shared = BigInt::from(bill.shared_amount().minor_amount());
+        let not_shared =
+            BigInt::from(bill.amount_due().minor_amount()) - bill.shared_amount().minor_amount();
+        BigRational::new(shared, self.count().into())
+            + BigRational::from_integer(not_shared) * personally_responsible
     }
 
-    fn build_split(&self, map: HashMap<&Roommate, Ratio<u32>>) -> ResponsibilitySplit {
-        let sum = map.values().sum::<Ratio<u32>>();
+    /// Returns an [`InvalidSplit`] error unless the proportions add up to 1
+    /// (or 0, in which case everyone is equally responsible) and each
+    /// belongs to someone in the group
+    ///
+    /// [`InvalidSplit`]: enum.Error.html#variant.InvalidSplit
+    pub(crate) fn build_split(
+        &self,
+        map: HashMap<&Roommate, Ratio<u32>>,
+    ) -> Result<ResponsibilitySplit<'_>, Error> {
+        self.build_exact_split(
+            map.into_iter()
+                .map(|(roommate, proportion)| (roommate, widen(proportion)))
+                .collect(),
+        )
+    }
+
+    /// Like [`build_split`], but with arbitrary-precision proportions
+    ///
+    /// [`build_split`]: struct.RoommateGroup.html#method.build_split
+    pub(crate) fn build_exact_split(
+        &self,
+        map: HashMap<&Roommate, BigRational>,
+    ) -> Result<ResponsibilitySplit<'_>, Error> {
+        if map.keys().any(|r| self.iter().all(|member| member != *r)) {
+            return Err(Error::InvalidSplit);
+        }
+        let sum = map.values().sum::<BigRational>();
         let all_roommates = self.iter();
-        let map: HashMap<_, _> = if sum == Ratio::from_integer(1) {
+        let map: HashMap<_, _> = if sum.is_one() {
             all_roommates
-                .map(|r| {
-                    (
-                        r,
-                        *map.get(r)
-                            .unwrap_or_else(|| panic!("roommate not in RoommateGroup {}", r)),
-                    )
-                })
+                .map(|r| (r, map.get(r).cloned().unwrap_or_else(BigRational::zero)))
                 .collect()
-        } else if sum == Ratio::from_integer(0) {
+        } else if sum.is_zero() {
             all_roommates
-                .map(|r| (r, Ratio::new(1u32, self.count())))
+                .map(|r| (r, BigRational::new(One::one(), self.count().into())))
                 .collect()
         } else {
-            panic!("sum must be 1 or 0")
+            return Err(Error::InvalidSplit);
         };
-        ResponsibilitySplit(map)
+        Ok(ResponsibilitySplit(map))
     }
 }
 
@@ -118,42 +181,31 @@ impl ResponsibilityRecord<'_> {
         &self,
         roommate: &Roommate,
         billing_period: DateInterval,
-    ) -> Ratio<u32> {
-        let roommate_intervals = self
-            .iter()
-            .filter(|i| i.roommate() == roommate)
-            .cloned()
-            .collect::<ResponsibilityRecord>();
-        let total_cost = self.occupancy_over(billing_period);
+    ) -> Result<Ratio<u32>, Error> {
+        let total_cost = self.checked_occupancy_over(billing_period)?;
         if total_cost == 0 {
-            return Ratio::from_integer(0);
+            return Ok(Ratio::from_integer(0));
         }
-        Ratio::new(
-            roommate_intervals.occupancy_over(billing_period),
+        Ok(Ratio::new(
+            self.roommate_occupancy_over(roommate, billing_period)?,
             total_cost,
-        )
+        ))
     }
 }
 
-pub struct ResponsibilitySplit<'a>(HashMap<&'a Roommate, Ratio<u32>>);
+pub struct ResponsibilitySplit<'a>(HashMap<&'a Roommate, BigRational>);
 
 impl<'a> ResponsibilitySplit<'a> {
-    pub fn hash_map(&self) -> HashMap<&'a Roommate, Ratio<u32>> {
-        self.0.iter().map(|(k, v)| (*k, *v)).collect()
+    /// Returns the proportion that each roommate is responsible for
+    pub fn hash_map(&self) -> HashMap<&'a Roommate, BigRational> {
+        self.0.iter().map(|(k, v)| (*k, v.clone())).collect()
     }
 }
 
-trait MulRatio<T> {
-    fn mul_rational(&self, r: Ratio<T>) -> Self;
-}
-
-impl MulRatio<u32> for Money {
-    fn mul_rational(&self, r: Ratio<u32>) -> Self {
-        self.checked_mul(*r.numer() as i64)
-            .expect("overflow")
-            .checked_div(*r.denom() as i64)
-            .expect("overflow")
-    }
+/// Converts a proportion into an arbitrary-precision rational so that it can
+/// be combined with others without overflowing
+fn widen(proportion: Ratio<u32>) -> BigRational {
+    BigRational::new((*proportion.numer()).into(), (*proportion.denom()).into())
 }
 
 #[cfg(test)]
@@ -161,14 +213,15 @@ mod tests {
     use super::*;
     use crate::bill::Bill;
     use crate::interval::{DateInterval, ResponsibilityInterval};
+    use crate::rounding::LargestRemainder;
     use std::collections::HashSet;
     use std::iter;
-    use steel_cent::currency::USD;
+    use steel_cent::currency::{EUR, USD};
 
     fn build_rs<'a>(
         rg: &'a RoommateGroup,
         pairs: Vec<(&str, u32, u32)>,
-    ) -> ResponsibilitySplit<'a> {
+    ) -> Result<ResponsibilitySplit<'a>, Error> {
         rg.build_split(
             pairs
                 .into_iter()
@@ -183,20 +236,74 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn sum_over_one() {
         let rg: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
-        let _rs = build_rs(&rg, vec![("a", 2, 3), ("b", 1, 3), ("c", 1, 3)]);
+        let rs = build_rs(&rg, vec![("a", 2, 3), ("b", 1, 3), ("c", 1, 3)]);
+        assert!(matches!(rs, Err(Error::InvalidSplit)));
+    }
+
+    #[test]
+    fn roommate_outside_group() {
+        let rg: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let stranger = Roommate::new("c");
+        let map = vec![(&stranger, Ratio::from_integer(1))]
+            .into_iter()
+            .collect();
+        assert!(matches!(rg.build_split(map), Err(Error::InvalidSplit)));
+    }
+
+    #[test]
+    fn empty_bill_list() {
+        let rg: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        assert_eq!(
+            rg.split_bill_list(vec![], &mut LargestRemainder),
+            Err(Error::EmptyBillList)
+        );
+    }
+
+    #[test]
+    fn bills_in_different_currencies() {
+        let rg: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let split = build_rs(&rg, vec![]).unwrap();
+        let bills = [
+            new_bill(Money::of_major(USD, 10), Money::zero(USD)),
+            new_bill(Money::of_major(EUR, 10), Money::zero(EUR)),
+        ];
+        assert_eq!(
+            rg.split_bill_list(bills.iter().map(|b| (b, &split)), &mut LargestRemainder),
+            Err(Error::MixedCurrencies)
+        );
+    }
+
+    #[test]
+    fn proportions_with_large_denominators() {
+        let rg: RoommateGroup = vec!["a", "b", "c", "d"].into_iter().collect();
+        // a + b and c + d are each 1/2, but any other partial sum has a
+        // denominator too big for a u32
+        let rs = build_rs(
+            &rg,
+            vec![
+                ("a", 1, 131_072),
+                ("b", 65_535, 131_072),
+                ("c", 1, 131_074),
+                ("d", 65_536, 131_074),
+            ],
+        )
+        .unwrap();
+        let bill = new_bill(Money::of_major(USD, 1_000), Money::zero(USD));
+        let share = rg.split(&bill, &rs, &mut LargestRemainder).unwrap();
+        let actual_total = share.values().fold(Money::zero(USD), |a, x| a + x);
+        assert_eq!(actual_total, Money::of_major(USD, 1_000));
     }
 
     #[test]
     fn empty_list() {
         let rg: RoommateGroup = vec!["a", "b", "c"].into_iter().collect();
-        let rs = build_rs(&rg, vec![]);
+        let rs = build_rs(&rg, vec![]).unwrap();
         assert_eq!(
             rs.hash_map().into_iter().collect::<HashSet<_>>(),
             rg.iter()
-                .zip(iter::repeat(Ratio::<u32>::new(1, 3)).take(3))
+                .zip(iter::repeat(BigRational::new(1.into(), 3.into())))
                 .collect::<HashSet<_>>(),
         );
     }
@@ -225,9 +332,9 @@ mod tests {
         let table: HashMap<_, _> = split.hash_map();
         assert_eq!(
             table.get(group.borrow_by_name("me").unwrap()).unwrap(),
-            &Ratio::new(2 * 3, 4 * 4 + 2 * 3),
+            &BigRational::new((2 * 3).into(), (4 * 4 + 2 * 3).into()),
         );
-        assert_eq!(table.values().sum::<Ratio<u32>>(), Ratio::from_integer(1),);
+        assert_eq!(table.values().sum::<BigRational>(), BigRational::one());
     }
 
     #[test]
@@ -250,7 +357,7 @@ mod tests {
                 .hash_map()
                 .get(group.borrow_by_name("me").unwrap())
                 .unwrap(),
-            Ratio::new(1, 2)
+            BigRational::new(1.into(), 2.into())
         );
     }
 
@@ -269,19 +376,21 @@ mod tests {
     fn regular_bill() {
         let roomies: RoommateGroup = vec!["bob", "joe"].into_iter().collect();
         let usage_proportions = vec![Ratio::new(1, 4), Ratio::new(3, 4)];
-        let split = roomies.build_split(
-            vec![
-                roomies.borrow_by_name("bob").unwrap(),
-                roomies.borrow_by_name("joe").unwrap(),
-            ]
-            .into_iter()
-            .zip(usage_proportions)
-            .collect(),
-        );
+        let split = roomies
+            .build_split(
+                vec![
+                    roomies.borrow_by_name("bob").unwrap(),
+                    roomies.borrow_by_name("joe").unwrap(),
+                ]
+                .into_iter()
+                .zip(usage_proportions)
+                .collect(),
+            )
+            .unwrap();
         let total = Money::of_major_minor(USD, 99, 99);
         let shared_cost = Money::of_major_minor(USD, 35, 46);
         let bill = new_bill(total, shared_cost);
-        let share = &roomies.split(&bill, &split);
+        let share = &roomies.split(&bill, &split, &mut LargestRemainder).unwrap();
         let bob_share = *share.get(roomies.borrow_by_name("bob").unwrap()).unwrap();
         assert_eq!(bob_share, shared_cost / 2 + (total - shared_cost) * 0.25);
     }
@@ -290,27 +399,35 @@ mod tests {
     fn list_of_bills() {
         let roomies: RoommateGroup = vec!["bob", "joe"].into_iter().collect();
         let usage_proportions = vec![Ratio::new(1, 4), Ratio::new(3, 4)];
-        let split = roomies.build_split(
-            vec![
-                roomies.borrow_by_name("bob").unwrap(),
-                roomies.borrow_by_name("joe").unwrap(),
-            ]
-            .into_iter()
-            .zip(usage_proportions)
-            .collect(),
-        );
+        let split = roomies
+            .build_split(
+                vec![
+                    roomies.borrow_by_name("bob").unwrap(),
+                    roomies.borrow_by_name("joe").unwrap(),
+                ]
+                .into_iter()
+                .zip(usage_proportions)
+                .collect(),
+            )
+            .unwrap();
         let total = Money::of_major_minor(USD, 99, 99);
         let shared_cost = Money::of_major_minor(USD, 35, 46);
-        let bills = vec![
+        let bills = [
             new_bill(total, shared_cost),
             new_bill(total * 2, shared_cost * 2),
         ];
-        let share =
-            roomies.split_bill_list(bills.iter().map(|bill| (bill, &split)).collect::<Vec<_>>());
+        let share = roomies
+            .split_bill_list(
+                bills.iter().map(|bill| (bill, &split)).collect::<Vec<_>>(),
+                &mut LargestRemainder,
+            )
+            .unwrap();
         let bob_share = *share.get(roomies.borrow_by_name("bob").unwrap()).unwrap();
-        let expected = shared_cost.checked_mul_f(1.5).unwrap()
-            + (total - shared_cost).checked_mul_f(0.25).unwrap() * 3;
+        // $33.8625 rounds down, but bob wins the tie for the half cent in $67.725
+        let expected = Money::of_minor(USD, 33_86) + Money::of_minor(USD, 67_73);
         assert_eq!(bob_share, expected);
+        let actual_total = share.values().fold(Money::zero(USD), |a, x| a + x);
+        assert_eq!(actual_total, total * 3);
         assert_eq!(
             bills[0].amount_due(),
             bills[0].amount_due(),
@@ -322,23 +439,27 @@ mod tests {
     fn list_of_zero_valued_bills() {
         let roomies: RoommateGroup = vec!["bob", "joe"].into_iter().collect();
         let usage_proportions = vec![Ratio::new(1, 4), Ratio::new(3, 4)];
-        let split = roomies.build_split(
-            vec![
-                roomies.borrow_by_name("bob").unwrap(),
-                roomies.borrow_by_name("joe").unwrap(),
-            ]
-            .into_iter()
-            .zip(usage_proportions)
-            .collect(),
-        );
+        let split = roomies
+            .build_split(
+                vec![
+                    roomies.borrow_by_name("bob").unwrap(),
+                    roomies.borrow_by_name("joe").unwrap(),
+                ]
+                .into_iter()
+                .zip(usage_proportions)
+                .collect(),
+            )
+            .unwrap();
         let total = Money::of_major_minor(USD, 0, 0);
         let shared_cost = Money::of_major_minor(USD, 0, 0);
-        let bills = vec![
+        let bills = [
             new_bill(total, shared_cost),
             new_bill(total * 2, shared_cost * 2),
         ];
         let bill_list: Vec<_> = bills.iter().map(|bill| (bill, &split)).collect();
-        let share = roomies.split_bill_list(bill_list);
+        let share = roomies
+            .split_bill_list(bill_list, &mut LargestRemainder)
+            .unwrap();
         let bob_share = *share.get(roomies.borrow_by_name("bob").unwrap()).unwrap();
         let expected = Money::zero(USD);
         assert_eq!(bob_share, expected);
@@ -353,20 +474,24 @@ mod tests {
     fn no_reponsibilities() {
         let roomies: RoommateGroup = vec!["bob", "joe"].into_iter().collect();
         let usage_proportions = vec![Ratio::from_integer(0), Ratio::from_integer(0)];
-        let split = roomies.build_split(
-            vec![
-                roomies.borrow_by_name("bob").unwrap(),
-                roomies.borrow_by_name("joe").unwrap(),
-            ]
-            .into_iter()
-            .zip(usage_proportions)
-            .collect(),
-        );
+        let split = roomies
+            .build_split(
+                vec![
+                    roomies.borrow_by_name("bob").unwrap(),
+                    roomies.borrow_by_name("joe").unwrap(),
+                ]
+                .into_iter()
+                .zip(usage_proportions)
+                .collect(),
+            )
+            .unwrap();
         let total = Money::of_major_minor(USD, 30, 0);
         let shared_cost = Money::of_major_minor(USD, 25, 0);
-        let bills = vec![new_bill(total, shared_cost)];
+        let bills = [new_bill(total, shared_cost)];
         let bill_list: Vec<_> = bills.iter().map(|bill| (bill, &split)).collect();
-        let share = roomies.split_bill_list(bill_list);
+        let share = roomies
+            .split_bill_list(bill_list, &mut LargestRemainder)
+            .unwrap();
         let bob_share = *share.get(roomies.borrow_by_name("bob").unwrap()).unwrap();
         let joe_share = *share.get(roomies.borrow_by_name("joe").unwrap()).unwrap();
         assert_eq!(bob_share, joe_share);
@@ -390,20 +515,36 @@ mod tests {
     }
 
     #[test]
-    #[ignore]
     fn rounding_issue_everyone_pays_the_same() {
         let roomies = vec!["a", "b", "c"].into_iter().collect::<RoommateGroup>();
         let usage_proportions = vec![0, 0, 0]
             .into_iter()
-            .map(|p| Ratio::from_integer(p))
+            .map(Ratio::from_integer)
             .collect::<Vec<_>>();
-        let split = roomies.build_split(roomies.iter().zip(usage_proportions).collect());
+        let split = roomies
+            .build_split(roomies.iter().zip(usage_proportions).collect())
+            .unwrap();
         let total = Money::of_major_minor(USD, 20, 00);
         let shared_cost = Money::of_major_minor(USD, 10, 00);
-        let bills = vec![new_bill(total, shared_cost)];
+        let bills = [new_bill(total, shared_cost)];
         let bill_list: Vec<_> = bills.iter().map(|bill| (bill, &split)).collect();
-        let share = roomies.split_bill_list(bill_list);
+        let share = roomies
+            .split_bill_list(bill_list, &mut LargestRemainder)
+            .unwrap();
         let actual_total = share.values().fold(Money::zero(USD), |a, x| a + x);
         assert_eq!(total, actual_total);
     }
+
+    #[test]
+    fn leftover_cents_go_to_largest_remainders() {
+        let roomies = vec!["a", "b", "c"].into_iter().collect::<RoommateGroup>();
+        let split = build_rs(&roomies, vec![("a", 1, 6), ("b", 2, 6), ("c", 3, 6)]).unwrap();
+        let bill = new_bill(Money::of_minor(USD, 1_00), Money::zero(USD));
+        let share = roomies.split(&bill, &split, &mut LargestRemainder).unwrap();
+        let get = |name| *share.get(roomies.borrow_by_name(name).unwrap()).unwrap();
+        // exact shares are 16.67, 33.33 and 50 cents
+        assert_eq!(get("a"), Money::of_minor(USD, 17));
+        assert_eq!(get("b"), Money::of_minor(USD, 33));
+        assert_eq!(get("c"), Money::of_minor(USD, 50));
+    }
 }
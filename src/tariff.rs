@@ -0,0 +1,470 @@
+use num::{BigRational, Zero};
+use std::collections::HashMap;
+use steel_cent::Money;
+
+use super::bill::Bill;
+use super::estimation::{Coefficient, Covariates, Design, Estimate, EstimationModel};
+use super::interval::ResponsibilityRecord;
+use super::roommate::RoommateGroup;
+use super::split::ResponsibilitySplit;
+use crate::{Error, EstimationError};
+
+/// How a utility charges for metered usage
+///
+/// A fixed customer charge, volumetric rates (in minor units per unit of
+/// usage) that step up in tiers, and taxes on the total.
+///
+/// # Examples
+/// ```
+/// use roommates::sharing::Tariff;
+/// use steel_cent::{Money, currency::USD};
+///
+/// let tariff = Tariff::new(Money::of_minor(USD, 10_00), 20.0)
+///     .with_tier(500.0, 10.0)
+///     .with_tax_rate(0.05);
+/// // $10 + 500 × 10¢ + 100 × 20¢, plus 5%
+/// assert!((tariff.charge(600.0) - 84_00.0).abs() < 1e-9);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tariff {
+    customer_charge: Money,
+    /// The upper limit and rate of each tier, in increasing order
+    tiers: Vec<(f64, f64)>,
+    top_rate: f64,
+    tax_rate: f64,
+}
+
+impl Tariff {
+    /// Creates a tariff that charges `customer_charge` plus `rate` for every
+    /// unit of usage
+    pub fn new(customer_charge: Money, rate: f64) -> Self {
+        Tariff {
+            customer_charge,
+            tiers: vec![],
+            top_rate: rate,
+            tax_rate: 0.0,
+        }
+    }
+
+    /// Adds a tier that charges `rate` for the usage up to `up_to` units that
+    /// lower tiers don't cover
+    ///
+    /// Usage above every tier is charged the rate given to [`Tariff::new`].
+    ///
+    /// [`Tariff::new`]: struct.Tariff.html#method.new
+    pub fn with_tier(mut self, up_to: f64, rate: f64) -> Self {
+        let i = self.tiers.partition_point(|(limit, _)| *limit < up_to);
+        self.tiers.insert(i, (up_to, rate));
+        self
+    }
+
+    /// Sets the taxes, as a proportion of the customer and volumetric charges
+    pub fn with_tax_rate(mut self, tax_rate: f64) -> Self {
+        self.tax_rate = tax_rate;
+        self
+    }
+
+    pub fn customer_charge(&self) -> Money {
+        self.customer_charge
+    }
+
+    pub fn tax_rate(&self) -> f64 {
+        self.tax_rate
+    }
+
+    /// The charge (in minor units, before taxes) for `usage` units, which
+    /// is zero for no usage or less
+    pub fn volumetric_charge(&self, usage: f64) -> f64 {
+        let mut charge = 0.0;
+        let mut lower = 0.0;
+        for (limit, rate) in &self.tiers {
+            charge += rate * (usage.min(*limit) - lower).max(0.0);
+            lower = limit.max(lower);
+        }
+        charge + self.top_rate * (usage - lower).max(0.0)
+    }
+
+    /// The rate (before taxes) of the next unit after `usage` units
+    pub fn marginal_rate(&self, usage: f64) -> f64 {
+        self.tiers
+            .iter()
+            .find(|(limit, _)| usage < *limit)
+            .map_or(self.top_rate, |(_, rate)| *rate)
+    }
+
+    /// The whole charge (in minor units) for `usage` units, including the
+    /// customer charge and taxes
+    pub fn charge(&self, usage: f64) -> f64 {
+        let before_taxes =
+            self.customer_charge.minor_amount() as f64 + self.volumetric_charge(usage);
+        before_taxes * (1.0 + self.tax_rate)
+    }
+}
+
+/// Estimates the shared cost from metered usage and the current bill's
+/// tariff
+///
+/// Fits `usage ~ Oc + covariates` by least squares to the history bills'
+/// [`usage`], which unlike their amounts due doesn't depend on the tariff.
+/// The shared cost is what the [`tariff`] charges for the usage fitted at
+/// zero occupancy (the customer charge and the lowest tiers, with taxes),
+/// plus the bill's fixed cost. The `Oc` coefficient is the cost of a
+/// person-day at the tier of the predicted usage, and the usage coefficients
+/// are reported in units of usage.
+///
+/// # Examples
+/// ```
+/// use roommates::DateInterval;
+/// use roommates::sharing::{Bill, Covariates, EstimationModel, MeteredUsage, Tariff};
+/// use steel_cent::{Money, currency::USD};
+///
+/// // $10 plus 10¢ a unit, and 100 units with nobody home
+/// let tariff = Tariff::new(Money::of_minor(USD, 10_00), 10.0);
+/// let bills: Vec<_> = [30, 60, 90, 45, 75]
+///     .iter()
+///     .zip(1..)
+///     .map(|(occupancy, month)| {
+///         let usage = 100.0 + 5.0 * f64::from(*occupancy);
+///         let period = DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap();
+///         let amount = Money::of_minor(USD, tariff.charge(usage).round() as i64);
+///         let bill = Bill::new(amount, period).with_usage(usage).with_tariff(tariff.clone());
+///         (bill, *occupancy)
+///     })
+///     .collect();
+/// let none = Covariates::new();
+/// let history: Vec<_> = bills[..4].iter().map(|(bill, oc)| (bill, *oc, &none)).collect();
+/// let (bill, occupancy) = &bills[4];
+/// let estimate = MeteredUsage::default()
+///     .estimate(bill, (*occupancy, &none), &history)
+///     .unwrap();
+/// // $10 + 100 × 10¢
+/// assert!((estimate.shared_cost - 20_00.0).abs() < 1e-6);
+/// ```
+///
+/// [`usage`]: struct.Bill.html#method.usage
+/// [`tariff`]: struct.Bill.html#method.tariff
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeteredUsage {
+    /// Whether to fit usage per day, so that bills of different lengths
+    /// compare fairly
+    pub per_day: bool,
+}
+
+impl EstimationModel for MeteredUsage {
+    fn name(&self) -> String {
+        if self.per_day {
+            String::from("metered usage per day and tariff")
+        } else {
+            String::from("metered usage and tariff")
+        }
+    }
+
+    fn estimate(
+        &self,
+        bill: &Bill,
+        notes: (u32, &Covariates),
+        history: &[(&Bill, u32, &Covariates)],
+    ) -> Result<Estimate, Error> {
+        let tariff = bill
+            .tariff()
+            .ok_or(Error::Estimation(EstimationError::MissingTariff))?;
+        if tariff.customer_charge().currency != bill.amount_due().currency {
+            return Err(Error::MixedCurrencies);
+        }
+        let mut design = Design::new(bill, notes, history)?;
+        design.y = history
+            .iter()
+            .map(|(bill, _, _)| {
+                bill.usage()
+                    .ok_or(Error::Estimation(EstimationError::MissingUsage {
+                        start: bill.usage_period().start(),
+                        end: bill.usage_period().end(),
+                    }))
+            })
+            .collect::<Result<_, Error>>()?;
+        design.fixed_cost = 0.0;
+        let usage = design.fit(self.per_day, None)?;
+        let fixed_cost = bill.fixed_cost().minor_amount() as f64;
+        let customer_charge = tariff.customer_charge().minor_amount() as f64;
+        let baseline_usage = usage.shared_cost;
+        let volumetric_charge = tariff.volumetric_charge(baseline_usage);
+        let contributions = vec![
+            (String::from("customer charge"), customer_charge),
+            (String::from("baseline usage"), volumetric_charge),
+            (
+                String::from("taxes"),
+                (customer_charge + volumetric_charge) * tariff.tax_rate(),
+            ),
+            (String::from("fixed cost"), fixed_cost),
+        ];
+        let predicted_usage = usage.predicted.unwrap_or(baseline_usage);
+        let marginal_rate = tariff.marginal_rate(predicted_usage) * (1.0 + tariff.tax_rate());
+        let mut coefficients = usage
+            .coefficients
+            .iter()
+            .filter(|c| c.name == "Oc")
+            .map(|c| Coefficient {
+                name: c.name.clone(),
+                value: c.value * marginal_rate,
+                standard_error: c.standard_error * marginal_rate,
+                p_value: c.p_value,
+            })
+            .collect::<Vec<_>>();
+        coefficients.extend(usage.coefficients.into_iter().map(|c| Coefficient {
+            name: format!("{} (usage)", c.name),
+            ..c
+        }));
+        Ok(Estimate {
+            shared_cost: contributions.iter().map(|(_, amount)| amount).sum(),
+            rsquared: usage.rsquared,
+            predicted: Some(fixed_cost + tariff.charge(predicted_usage)),
+            coefficients,
+            prediction_interval: usage.prediction_interval.map(|(low, high)| {
+                (
+                    fixed_cost + tariff.charge(low),
+                    fixed_cost + tariff.charge(high),
+                )
+            }),
+            contributions,
+            outliers: usage.outliers,
+            parameters: vec![(String::from("baseline usage"), baseline_usage)],
+            variance_inflation: usage.variance_inflation,
+        })
+    }
+}
+
+impl RoommateGroup {
+    /// Returns the proportion of the non-shared cost of a metered bill that
+    /// each roommate is responsible for, charging the higher tiers of its
+    /// tariff to the roommates whose occupancy pushed usage into them
+    ///
+    /// The `baseline_usage` (such as the [`MeteredUsage`] estimate's, and
+    /// zero if it is negative) is shared and fills the lowest tiers. The rest
+    /// of the bill's usage is attributed to roommates by occupancy and charged
+    /// by serial cost sharing: every roommate pays equally for the usage up to
+    /// the lightest user's, the rest pay equally for the usage up to the next
+    /// lightest user's, and so on, so that only the heaviest users pay the
+    /// most expensive tiers.
+    ///
+    /// Returns a [`MissingUsage`] or [`MissingTariff`] error if the bill
+    /// doesn't have them, and an [`Overflow`] error if the occupancy cannot
+    /// be counted.
+    ///
+    /// # Examples
+    /// ```
+    /// use num::BigRational;
+    /// use roommates::{DateInterval, ResponsibilityInterval, ResponsibilityRecord, RoommateGroup};
+    /// use roommates::sharing::{Bill, Tariff};
+    /// use steel_cent::{Money, currency::USD};
+    ///
+    /// let group: RoommateGroup = vec!["Ann", "Bo"].into_iter().collect();
+    /// let month = DateInterval::new((2020, 1, 1), (2020, 1, 31)).unwrap();
+    /// let record: ResponsibilityRecord = vec![
+    ///     ResponsibilityInterval::new(group.borrow_by_name("Ann").unwrap(), month, 0),
+    ///     ResponsibilityInterval::new(group.borrow_by_name("Bo").unwrap(), month, 0),
+    /// ].into_iter().collect();
+    /// let bill = Bill::new(Money::of_minor(USD, 50_00), month)
+    ///     .with_usage(400.0)
+    ///     .with_tariff(Tariff::new(Money::zero(USD), 20.0).with_tier(300.0, 10.0));
+    /// let split = group.tiered_responsibilities(&record, &bill, 100.0).unwrap();
+    /// // equal usage shares the tiers equally
+    /// assert_eq!(split.hash_map()[group.borrow_by_name("Bo").unwrap()], BigRational::new(1.into(), 2.into()));
+    /// ```
+    ///
+    /// [`MeteredUsage`]: sharing/struct.MeteredUsage.html
+    /// [`MissingUsage`]: enum.EstimationError.html#variant.MissingUsage
+    /// [`MissingTariff`]: enum.EstimationError.html#variant.MissingTariff
+    /// [`Overflow`]: enum.Error.html#variant.Overflow
+    pub fn tiered_responsibilities(
+        &self,
+        responsibility_intervals: &ResponsibilityRecord,
+        bill: &Bill,
+        baseline_usage: f64,
+    ) -> Result<ResponsibilitySplit<'_>, Error> {
+        let period = bill.usage_period();
+        let usage = bill
+            .usage()
+            .ok_or(Error::Estimation(EstimationError::MissingUsage {
+                start: period.start(),
+                end: period.end(),
+            }))?;
+        let tariff = bill
+            .tariff()
+            .ok_or(Error::Estimation(EstimationError::MissingTariff))?;
+        let total_occupancy = responsibility_intervals.checked_occupancy_over(period)?;
+        // NaN counts as zero
+        let baseline_usage = if baseline_usage > 0.0 {
+            baseline_usage
+        } else {
+            0.0
+        };
+        let personal_usage = (usage - baseline_usage).max(0.0);
+        let mut demands = self
+            .iter()
+            .map(|roommate| {
+                let occupancy =
+                    responsibility_intervals.roommate_occupancy_over(roommate, period)?;
+                let demand = if total_occupancy == 0 {
+                    0.0
+                } else {
+                    personal_usage * f64::from(occupancy) / f64::from(total_occupancy)
+                };
+                Ok((roommate, demand))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        demands.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        // the cost of personal usage on top of the baseline
+        let cost = |personal: f64| {
+            tariff.volumetric_charge(baseline_usage + personal)
+                - tariff.volumetric_charge(baseline_usage)
+        };
+        // the shares are summed exactly so that they add up to the total
+        let mut shares = vec![];
+        let (mut share, mut total) = (BigRational::zero(), BigRational::zero());
+        let (mut served, mut previous_cost) = (0.0, 0.0);
+        for (i, (roommate, demand)) in demands.iter().enumerate() {
+            let remaining = demands.len() - i;
+            let level_cost = cost(served + remaining as f64 * demand);
+            // a cost that isn't finite counts as nothing
+            let step = BigRational::from_float((level_cost - previous_cost).max(0.0))
+                .unwrap_or_else(BigRational::zero);
+            share += &step / BigRational::from_integer(remaining.into());
+            total += step;
+            shares.push((*roommate, share.clone()));
+            served += demand;
+            previous_cost = level_cost;
+        }
+        // with nothing to share, the bill is split evenly
+        let map = if total.is_zero() {
+            HashMap::new()
+        } else {
+            shares
+                .into_iter()
+                .map(|(roommate, share)| (roommate, share / &total))
+                .collect()
+        };
+        self.build_exact_split(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::{DateInterval, ResponsibilityInterval};
+    use crate::test_fixtures::{roommate, NONE};
+    use num::BigRational;
+    use steel_cent::currency::USD;
+
+    fn tariff() -> Tariff {
+        Tariff::new(Money::of_minor(USD, 10_00), 20.0)
+            .with_tier(300.0, 10.0)
+            .with_tax_rate(0.05)
+    }
+
+    #[test]
+    fn heavy_user_pays_top_tier() {
+        let group: RoommateGroup = vec!["a", "b"].into_iter().collect();
+        let month = DateInterval::new((2020, 1, 1), (2020, 1, 28)).unwrap();
+        // b has two guests, so uses three times as much as a
+        let record: ResponsibilityRecord = vec![
+            ResponsibilityInterval::new(roommate(&group, "a"), month, 0),
+            ResponsibilityInterval::new(roommate(&group, "b"), month, 2),
+        ]
+        .into_iter()
+        .collect();
+        let bill = Bill::new(Money::of_minor(USD, 80_00), month)
+            .with_usage(500.0)
+            .with_tariff(tariff());
+        let split = group
+            .tiered_responsibilities(&record, &bill, 100.0)
+            .unwrap()
+            .hash_map();
+        // a's 100 units are in the 10¢ tier, as are the first 100 of b's 300,
+        // and b's other 200 are at 20¢
+        assert_eq!(
+            split[roommate(&group, "a")],
+            BigRational::new(1.into(), 6.into())
+        );
+        assert_eq!(
+            split[roommate(&group, "b")],
+            BigRational::new(5.into(), 6.into())
+        );
+
+        let nothing_shared = group
+            .tiered_responsibilities(&record, &bill, 0.0)
+            .unwrap()
+            .hash_map();
+        for baseline_usage in [-100.0, f64::NAN] {
+            let split = group
+                .tiered_responsibilities(&record, &bill, baseline_usage)
+                .unwrap()
+                .hash_map();
+            assert_eq!(split, nothing_shared);
+        }
+
+        let unused = bill.clone().with_usage(50.0);
+        let split = group
+            .tiered_responsibilities(&record, &unused, 100.0)
+            .unwrap()
+            .hash_map();
+        assert_eq!(
+            split[roommate(&group, "a")],
+            BigRational::new(1.into(), 2.into())
+        );
+    }
+
+    #[test]
+    fn tiers() {
+        let tariff = tariff().with_tier(100.0, 5.0);
+        assert_eq!(tariff.volumetric_charge(-10.0), 0.0);
+        assert_eq!(tariff.volumetric_charge(50.0), 250.0);
+        assert_eq!(tariff.volumetric_charge(200.0), 500.0 + 1000.0);
+        assert_eq!(tariff.volumetric_charge(400.0), 500.0 + 2000.0 + 2000.0);
+        assert_eq!(tariff.marginal_rate(100.0), 10.0);
+        assert_eq!(tariff.marginal_rate(300.0), 20.0);
+    }
+
+    #[test]
+    fn shared_cost_from_tariff() {
+        // 100 units with nobody home and 5 more per person-day
+        let bills = [30, 60, 90, 45, 75, 15, 60]
+            .iter()
+            .zip(1..)
+            .map(|(occupancy, month)| {
+                let period = DateInterval::new((2020, month, 1), (2020, month, 28)).unwrap();
+                let usage = 100.0 + 5.0 * *occupancy as f64;
+                let amount = tariff().charge(usage).round() as i64;
+                let bill = Bill::new(Money::of_minor(USD, amount), period)
+                    .with_usage(usage)
+                    .with_tariff(tariff());
+                (bill, *occupancy)
+            })
+            .collect::<Vec<_>>();
+        let (current, occupancy) = &bills[6];
+        let history = bills[..6]
+            .iter()
+            .map(|(bill, oc)| (bill, *oc, &NONE))
+            .collect::<Vec<_>>();
+        let estimate = MeteredUsage::default()
+            .estimate(current, (*occupancy, &NONE), &history)
+            .unwrap();
+        // ($10 + 100 × 10¢) × 1.05
+        assert!((estimate.shared_cost - 21_00.0).abs() < 1e-6);
+        // 5 units of the 400 predicted at 20¢, plus taxes
+        assert!((estimate.marginal_cost().unwrap() - 105.0).abs() < 1e-6);
+        assert!((estimate.predicted.unwrap() - tariff().charge(400.0)).abs() < 1e-6);
+
+        let unmetered = Bill::new(Money::of_minor(USD, 50_00), bills[0].0.usage_period());
+        let mut history = history;
+        history[0].0 = &unmetered;
+        assert_eq!(
+            MeteredUsage::default()
+                .estimate(current, (*occupancy, &NONE), &history)
+                .err(),
+            Some(Error::Estimation(EstimationError::MissingUsage {
+                start: unmetered.usage_period().start(),
+                end: unmetered.usage_period().end(),
+            }))
+        );
+    }
+}
@@ -0,0 +1,10 @@
+use super::estimation::Covariates;
+use super::roommate::{Roommate, RoommateGroup};
+
+/// The covariates of a bill that has none
+pub(crate) static NONE: Covariates = Covariates::new();
+
+/// The roommate named `name`, who must be in `group`
+pub(crate) fn roommate<'a>(group: &'a RoommateGroup, name: &str) -> &'a Roommate {
+    group.borrow_by_name(name).unwrap()
+}